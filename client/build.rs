@@ -21,4 +21,5 @@ fn main() {
         .file("jpeg_interface.c")
         .compile("jpeg_interface");
     println!("cargo:rustc-link-lib=dylib=jpeg");
+    println!("cargo:rustc-link-lib=dylib=png");
 }
@@ -0,0 +1,263 @@
+// This file is part of flashvnc, a VNC client.
+// Copyright 2018 Patrick Plagwitz
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use MainError;
+use protocol::rfb::{self,CutText,ExtendedClipboard};
+
+use flate2;
+use std::io::{Read,Write};
+
+const MAX_SIZE : u32 = 20 * 1024 * 1024;
+//the frontends only have text, so RTF and HTML are not offered
+const SUPPORTED_FORMATS : u32 = rfb::CLIPBOARD_TEXT;
+
+fn error(reason : &str) -> MainError {
+    MainError::new(format!("Invalid extended clipboard data: {}", reason))
+}
+
+//text in the extended clipboard has CRLF line endings and a terminating NUL
+fn to_extended_text(text : &str) -> Vec<u8> {
+    let mut bytes = text.replace("\r\n", "\n").replace("\n", "\r\n")
+        .into_bytes();
+    bytes.push(0);
+    bytes
+}
+fn from_extended_text(bytes : &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).replace("\r\n", "\n")
+}
+
+fn take<'a>(data : &mut &'a [u8], len : usize)
+    -> Result<&'a [u8], MainError>
+{
+    if data.len() < len {
+        return Err(error("data is too short"));
+    }
+    let (taken, rest) = data.split_at(len);
+    *data = rest;
+    Ok(taken)
+}
+
+//every Provide message is a zlib stream of its own; only the text is kept
+pub fn decode_provided(formats : u32, data : &[u8])
+    -> Result<Option<String>, MainError>
+{
+    //a length field for each of the 16 possible formats
+    let max_len = MAX_SIZE as u64 + 16 * 4;
+    let mut inflated = Vec::new();
+    flate2::read::ZlibDecoder::new(data).take(max_len + 1)
+        .read_to_end(&mut inflated)
+        .map_err(|_| error("data is not zlib-compressed"))?;
+    if inflated.len() as u64 > max_len {
+        return Err(error("data is too large"));
+    }
+    let mut data = &inflated[..];
+    let mut text = None;
+    for bit in 0..16 {
+        let format = 1 << bit;
+        if formats & format == 0 {
+            continue;
+        }
+        let len = take(&mut data, 4)?.iter()
+            .fold(0, |value, &byte| value << 8 | byte as usize);
+        let bytes = take(&mut data, len)?;
+        if format == rfb::CLIPBOARD_TEXT {
+            text = Some(from_extended_text(bytes));
+        }
+    }
+    Ok(text)
+}
+
+fn encode_provided_text(text : &str) -> ExtendedClipboard {
+    let text = to_extended_text(text);
+    let mut zlib = flate2::write::ZlibEncoder::new(
+        Vec::new(), flate2::Compression::Default);
+    let len = text.len() as u32;
+    zlib.write_all(&[(len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8,
+                     len as u8]).unwrap();
+    zlib.write_all(&text).unwrap();
+    ExtendedClipboard::Provide {
+        formats: rfb::CLIPBOARD_TEXT,
+        data: zlib.finish().unwrap()
+    }
+}
+
+//decides which cut text messages to send; the local text is sent as Latin-1
+//until the server announces the extended clipboard
+pub struct Clipboard {
+    //the actions of the server and its maximum size of text
+    server_caps : Option<(u32, u32)>,
+    local_text : Option<String>,
+    server_text : Option<String>
+}
+impl Clipboard {
+    pub fn new() -> Self {
+        Self {
+            server_caps: None,
+            local_text: None,
+            server_text: None
+        }
+    }
+
+    fn provide(&self) -> Option<CutText> {
+        let max_text_size = self.server_caps.map_or(0, |(_, max)| max);
+        match self.local_text {
+            Some(ref text) if text.len() < max_text_size as usize =>
+                Some(CutText::Extended(encode_provided_text(text))),
+            _ => None
+        }
+    }
+
+    pub fn local_text_changed(&mut self, text : String) -> Option<CutText> {
+        if self.server_text.as_ref() == Some(&text) {
+            return None;
+        }
+        self.local_text = Some(text.clone());
+        match self.server_caps {
+            None => Some(CutText::Latin1(text)),
+            Some((flags, _)) if flags & rfb::CLIPBOARD_NOTIFY != 0 =>
+                Some(CutText::Extended(
+                        ExtendedClipboard::Notify(rfb::CLIPBOARD_TEXT))),
+            Some((flags, _)) if flags & rfb::CLIPBOARD_PROVIDE != 0 =>
+                self.provide(),
+            Some(_) => None
+        }
+    }
+
+    //not sent back when the view reports it as a change of its clipboard
+    pub fn server_text_changed(&mut self, text : String) {
+        self.server_text = Some(text);
+        self.local_text = None;
+    }
+
+    //Provide messages are handled by the reading side, which decodes them
+    pub fn handle_server_message(&mut self, message : ExtendedClipboard)
+        -> Option<CutText>
+    {
+        use self::ExtendedClipboard::*;
+
+        match message {
+            Caps { flags, max_sizes } => {
+                let text_size = if flags & rfb::CLIPBOARD_TEXT != 0 {
+                    max_sizes.first().cloned().unwrap_or(0)
+                } else {
+                    0
+                };
+                self.server_caps = Some((flags, text_size));
+                Some(CutText::Extended(Caps {
+                    flags: SUPPORTED_FORMATS | rfb::CLIPBOARD_REQUEST
+                        | rfb::CLIPBOARD_PEEK | rfb::CLIPBOARD_NOTIFY
+                        | rfb::CLIPBOARD_PROVIDE,
+                    max_sizes: vec![MAX_SIZE]
+                }))
+            },
+            Request(formats) if formats & rfb::CLIPBOARD_TEXT != 0 =>
+                self.provide(),
+            Request(_) => None,
+            Peek => {
+                let formats = if self.local_text.is_some() {
+                    rfb::CLIPBOARD_TEXT
+                } else {
+                    0
+                };
+                Some(CutText::Extended(Notify(formats)))
+            },
+            Notify(formats) if formats & rfb::CLIPBOARD_TEXT != 0 =>
+                Some(CutText::Extended(Request(rfb::CLIPBOARD_TEXT))),
+            Notify(_) | Provide { .. } => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod the_clipboard {
+    use super::*;
+
+    fn caps(flags : u32) -> ExtendedClipboard {
+        ExtendedClipboard::Caps {
+            flags: flags | rfb::CLIPBOARD_TEXT,
+            max_sizes: vec![100]
+        }
+    }
+
+    #[test]
+    fn should_send_latin1_text_until_the_server_sends_its_caps() {
+        let mut clipboard = Clipboard::new();
+        assert_eq!(clipboard.local_text_changed(String::from("a")),
+                   Some(CutText::Latin1(String::from("a"))));
+        assert!(clipboard.handle_server_message(
+                caps(rfb::CLIPBOARD_NOTIFY)).is_some());
+        assert_eq!(clipboard.local_text_changed(String::from("b")),
+                   Some(CutText::Extended(ExtendedClipboard::Notify(
+                               rfb::CLIPBOARD_TEXT))));
+    }
+
+    #[test]
+    fn should_provide_the_text_when_requested() {
+        let mut clipboard = Clipboard::new();
+        clipboard.handle_server_message(caps(rfb::CLIPBOARD_NOTIFY));
+        clipboard.local_text_changed(String::from("\u{fc}ber\n5\u{20ac}"));
+        match clipboard.handle_server_message(
+            ExtendedClipboard::Request(rfb::CLIPBOARD_TEXT))
+        {
+            Some(CutText::Extended(ExtendedClipboard::Provide {
+                formats, data
+            })) => {
+                assert_eq!(formats, rfb::CLIPBOARD_TEXT);
+                let decoded = decode_provided(formats, &data).unwrap();
+                assert_eq!(decoded.unwrap(), "\u{fc}ber\n5\u{20ac}");
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn should_not_provide_text_larger_than_the_server_allows() {
+        let mut clipboard = Clipboard::new();
+        clipboard.handle_server_message(caps(rfb::CLIPBOARD_PROVIDE));
+        assert!(clipboard.local_text_changed(String::from("a")).is_some());
+        assert!(clipboard.local_text_changed("a".repeat(100)).is_none());
+    }
+
+    #[test]
+    fn should_not_send_back_the_text_of_the_server() {
+        let mut clipboard = Clipboard::new();
+        clipboard.server_text_changed(String::from("a"));
+        assert!(clipboard.local_text_changed(String::from("a")).is_none());
+    }
+
+    fn compress(data : &[u8]) -> Vec<u8> {
+        let mut zlib = flate2::write::ZlibEncoder::new(
+            Vec::new(), flate2::Compression::Default);
+        zlib.write_all(data).unwrap();
+        zlib.finish().unwrap()
+    }
+
+    #[test]
+    fn should_decode_the_provided_text_with_lf_line_endings() {
+        let data = compress(&[0, 0, 0, 4,  b'a', b'\r', b'\n', 0,
+                              0, 0, 0, 3,  b'<', b'b', b'>']);
+        assert_eq!(decode_provided(rfb::CLIPBOARD_TEXT | rfb::CLIPBOARD_HTML,
+                                   &data).unwrap(),
+                   Some(String::from("a\n")));
+    }
+
+    #[test]
+    fn should_refuse_provided_data_that_inflates_beyond_the_maximum() {
+        let data = compress(&vec![0; MAX_SIZE as usize + 100]);
+        assert!(decode_provided(rfb::CLIPBOARD_TEXT, &data).is_err());
+    }
+}
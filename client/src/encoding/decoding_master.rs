@@ -14,7 +14,7 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use ::{SharedFb,FbSize,MainError,Bgrx,PIXEL_FORMAT_BYTES_PER_PIXEL,
+use ::{SharedFb,FbSize,MainError,Bgrx,
        FbSlice,FbAccess,Cursor,CursorSize,Hotspot,PixelFormat,
        FB_PIXEL_FORMAT};
 use std::io;
@@ -26,16 +26,38 @@ use std::ptr;
 
 use tight::ZlibDecoder;
 use tight::jpeg::Decoder as JpegDecoder;
+use tight::png;
 
-use infrastructure::thread_pool::{ThreadPool,Future,FutureCollection};
+use infrastructure::thread_pool::{ThreadPool,Future,FutureCollection,Error};
 use infrastructure::BitBuffer;
+use super::trle;
+use super::hextile;
+use super::pixel_converter::PixelConverter;
 
 pub enum EncodingMethod {
+    //in the pixel format of the connection, like the other pixels
     RawBgra(Vec<u8>),
     CopyFilter(TightData),
     PaletteFilter(Vec<Bgrx>, TightData),
+    GradientFilter(TightData),
     Jpeg(Vec<u8>),
+    Png(Vec<u8>),
     Fill(Bgrx),
+    Zrle(Vec<u8>),
+    TrleTile(rfb::TrleTile),
+    HextileTile(rfb::HextileTile),
+    //raw pixels
+    Zlib(Vec<u8>),
+    ZlibHex(Vec<(Bounds, rfb::ZlibHexTile)>),
+    //RRE and CoRRE
+    Rre {
+        background : Bgrx,
+        subrects : Vec<(Bounds, Bgrx)>
+    },
+    CopyRect {
+        src_x : usize,
+        src_y : usize
+    },
     CursorBgrx {
         pixels: Vec<u8>,
         bitmask: Vec<u8>
@@ -70,8 +92,8 @@ impl DecodingJob {
 //TODO use in other places
 //#[derive(Clone, Copy)]
 pub struct Bounds {
-    x : usize,
-    y : usize,
+    pub x : usize,
+    pub y : usize,
     size : FbSize
 }
 impl Bounds {
@@ -83,12 +105,26 @@ impl Bounds {
         }
     }
 
-    fn width(&self) -> usize {
+    pub fn width(&self) -> usize {
         self.size.width
     }
-    fn height(&self) -> usize {
+    pub fn height(&self) -> usize {
         self.size.height
     }
+
+    //row by row, smaller at the right and bottom edges
+    pub fn tiles(&self, tile_size : usize) -> Vec<Bounds> {
+        let mut tiles = Vec::new();
+        for y in (0..self.height()).step_by(tile_size) {
+            for x in (0..self.width()).step_by(tile_size) {
+                tiles.push(Bounds::new(
+                        self.x + x, self.y + y,
+                        FbSize::new(tile_size.min(self.width() - x),
+                                    tile_size.min(self.height() - y))));
+            }
+        }
+        tiles
+    }
 }
 
 struct Decoders {
@@ -115,65 +151,133 @@ type SharedCursor = Arc<Mutex<Cursor>>;
 type State = (Decoders, SharedFb, SharedCursor);
 
 type DecoderPool = ThreadPool<State>;
+//the streams of raw tiles and of the other ones
+type ZlibHexStreams = (ZlibDecoder, ZlibDecoder);
+
+fn decoder_pool(name_prefix : &str, size : usize,
+                fb : &SharedFb, cursor : &SharedCursor) -> DecoderPool {
+    let fb = fb.clone();
+    let cursor = cursor.clone();
+    ThreadPool::new(name_prefix, size,
+                    move || (Decoders::new(), fb.clone(), cursor.clone()))
+}
 
 pub struct DecodingMaster {
     general_decoders : DecoderPool,
     zlib_decoders : [DecoderPool; 4],
+    //ZRLE has a single zlib stream for the whole connection
+    zrle_decoder : DecoderPool,
+    zlib_encoding_decoder : DecoderPool,
+    zlib_hex_decoder : ThreadPool<ZlibHexStreams>,
     futures : RefCell<Vec<Future<MainError>>>,
+    //jobs use the one that was current when they were accepted
+    converter : RefCell<Arc<PixelConverter>>,
     framebuffer : SharedFb,
     no_of_threads : usize
 }
 impl DecodingMaster {
-    pub fn new(fb : SharedFb, cursor : SharedCursor) -> Self {
-        let cursor_clone_1 = cursor.clone();
-        let cursor_clone_2 = cursor.clone();
-        let cursor_clone_3 = cursor.clone();
-        let cursor_clone_4 = cursor.clone();
-        let fb_clone = fb.clone();
-        let fb_clone_1 = fb.clone();
-        let fb_clone_2 = fb.clone();
-        let fb_clone_3 = fb.clone();
-        let fb_clone_4 = fb.clone();
+    pub fn new(fb : SharedFb, cursor : SharedCursor,
+               converter : PixelConverter) -> Self {
         let no_of_threads = 4;
 
-        let general_decoders = ThreadPool::new(
-            "general-decoder", no_of_threads,
-            move || (Decoders::new(), fb_clone.clone(), cursor.clone()));
+        let general_decoders = decoder_pool(
+            "general-decoder", no_of_threads, &fb, &cursor);
         let zlib_decoders = [
-            ThreadPool::new("zlib-decoder-1", 1, move ||
-                            (Decoders::new(), fb_clone_1.clone(), 
-                             cursor_clone_1.clone())),
-            ThreadPool::new("zlib-decoder-2", 1, move ||
-                            (Decoders::new(), fb_clone_2.clone(),
-                            cursor_clone_2.clone())),
-            ThreadPool::new("zlib-decoder-3", 1, move ||
-                            (Decoders::new(), fb_clone_3.clone(),
-                            cursor_clone_3.clone())),
-            ThreadPool::new("zlib-decoder-4", 1, move ||
-                            (Decoders::new(), fb_clone_4.clone(),
-                            cursor_clone_4.clone()))];
+            decoder_pool("zlib-decoder-1", 1, &fb, &cursor),
+            decoder_pool("zlib-decoder-2", 1, &fb, &cursor),
+            decoder_pool("zlib-decoder-3", 1, &fb, &cursor),
+            decoder_pool("zlib-decoder-4", 1, &fb, &cursor)];
+        let zrle_decoder = decoder_pool("zrle-decoder", 1, &fb, &cursor);
+        let zlib_encoding_decoder = decoder_pool(
+            "zlib-encoding-decoder", 1, &fb, &cursor);
+        let zlib_hex_decoder = ThreadPool::new(
+            "zlib-hex-decoder", 1,
+            || (ZlibDecoder::new(), ZlibDecoder::new()));
 
         Self {
             general_decoders: general_decoders,
             zlib_decoders: zlib_decoders,
+            zrle_decoder: zrle_decoder,
+            zlib_encoding_decoder: zlib_encoding_decoder,
+            zlib_hex_decoder: zlib_hex_decoder,
             futures: RefCell::new(Vec::with_capacity(20)),
+            converter: RefCell::new(Arc::new(converter)),
             framebuffer: fb,
             no_of_threads: no_of_threads
         }
     }
 
+    pub fn set_pixel_converter(&self, converter : PixelConverter) {
+        *self.converter.borrow_mut() = Arc::new(converter);
+    }
+
     fn spawn_job(&self, pool : &DecoderPool,
                  bounds : Bounds, method : EncodingMethod) {
+        let converter = self.converter.borrow().clone();
         self.futures.borrow_mut().push(pool.spawn_fn(
-                move |&mut (ref mut decoders, ref fb, ref mut cursor)| {
-                    decode(
-                        fb, &cursor,
-                        &mut decoders.zlib_decoder,
-                        &mut decoders.jpeg_decoder,
-                        bounds, method) 
+                move |state| decode_in_pool(state, &converter,
+                                            bounds, method)));
+    }
+
+    //the zlib stream has to be inflated in order, but the tiles of the
+    //inflated data can be decoded in parallel
+    fn spawn_zrle_job(&self, bounds : Bounds, bytes : Vec<u8>) {
+        let general_decoders = self.general_decoders.spawner();
+        let converter = self.converter.borrow().clone();
+        self.futures.borrow_mut().push(self.zrle_decoder.spawn_fn(
+                move |&mut (ref mut decoders, _, _)| {
+                    let data = decoders.zlib_decoder.decode(&bytes[..])?;
+                    let tiles = trle::parse_zrle_tiles(
+                        data, &bounds, converter.cpixel_len())?;
+                    let futures = tiles.into_iter().map(|(bounds, tile)| {
+                        let converter = converter.clone();
+                        general_decoders.spawn_fn(move |state| decode_in_pool(
+                                state, &converter, bounds,
+                                EncodingMethod::TrleTile(tile)))
+                    }).collect();
+                    FutureCollection::new(futures).wait()?;
+                    Ok(())
                 }));
     }
 
+    //like ZRLE, but the colours of the tiles also depend on the ones
+    //before them
+    fn spawn_zlib_hex_job(&self, tiles : Vec<(Bounds, rfb::ZlibHexTile)>) {
+        let general_decoders = self.general_decoders.spawner();
+        let converter = self.converter.borrow().clone();
+        self.futures.borrow_mut().push(self.zlib_hex_decoder.spawn_fn(
+                move |&mut (ref mut raw_stream, ref mut stream)| {
+                    let (mut background, mut foreground) = (None, None);
+                    let mut futures = Vec::with_capacity(tiles.len());
+                    for (bounds, tile) in tiles {
+                        let tile = hextile::inflate_zlib_hex_tile(
+                            tile, bounds.width(), bounds.height(),
+                            converter.bytes_per_pixel(),
+                            raw_stream, stream)?;
+                        let tile = hextile::resolve_hextile_colors(
+                            tile, &mut background, &mut foreground)?;
+                        let converter = converter.clone();
+                        futures.push(general_decoders.spawn_fn(
+                                move |state| decode_in_pool(
+                                    state, &converter, bounds,
+                                    EncodingMethod::HextileTile(tile))));
+                    }
+                    FutureCollection::new(futures).wait()?;
+                    Ok(())
+                }));
+    }
+
+    //the source has to be decoded by the rectangles before and must not be
+    //changed by the ones after, so this waits for the jobs so far and
+    //copies before any other job is spawned
+    fn copy_rect(&self, src_x : usize, src_y : usize, bounds : Bounds) {
+        let result = self.finish().wait()
+            .map_err(|errors| Error::Value(MainError::from(errors)))
+            .and_then(|()| copy_rect(&self.framebuffer, src_x, src_y, bounds)
+                      .map_err(Error::Value));
+        self.futures.borrow_mut().push(Future::ready(result));
+    }
+
     pub fn accept(&self, job : DecodingJob) {
         use DecodingJob::*;
         use EncodingMethod::*;
@@ -190,6 +294,23 @@ impl DecodingMaster {
                         self.spawn_job(&self.zlib_decoders[stream_no],
                                        bounds, method);
                     },
+                    GradientFilter(CompressedRgb { stream_no, bytes: _ }) => {
+                        self.spawn_job(&self.zlib_decoders[stream_no],
+                                       bounds, method);
+                    },
+                    Zrle(bytes) => {
+                        self.spawn_zrle_job(bounds, bytes);
+                    },
+                    Zlib(_) => {
+                        self.spawn_job(&self.zlib_encoding_decoder,
+                                       bounds, method);
+                    },
+                    ZlibHex(tiles) => {
+                        self.spawn_zlib_hex_job(tiles);
+                    },
+                    CopyRect { src_x, src_y } => {
+                        self.copy_rect(src_x, src_y, bounds);
+                    },
                     _ => {
                         self.spawn_job(&self.general_decoders,
                                        bounds, method);
@@ -284,7 +405,68 @@ fn copy(dst : *mut u8, src : *const u8, pixels : usize) {
     }
 }
 
-fn decode(fb : &SharedFb, cursor : &SharedCursor,
+fn copy_rect(fb : &SharedFb, src_x : usize, src_y : usize, bounds : Bounds)
+    -> Result<(), MainError>
+{
+    let mut fb = fb.lock(FbAccess::Decoding);
+    let size = fb.size();
+    if src_x + bounds.width() > size.width
+        || src_y + bounds.height() > size.height
+        || bounds.x + bounds.width() > size.width
+        || bounds.y + bounds.height() > size.height
+    {
+        return Err(MainError::new(String::from(
+                    "CopyRect outside of the framebuffer")));
+    }
+    fb.copy_rect(src_x, src_y, bounds.x, bounds.y,
+                 bounds.width(), bounds.height());
+    Ok(())
+}
+
+fn fill<S : FbSlice + ?Sized>(fb : &mut S, bounds : &Bounds, color : &Bgrx) {
+    for y in 0..bounds.height() {
+        for x in 0..bounds.width() {
+            fb.set_pixel(
+                x + bounds.x,
+                y + bounds.y,
+                color.r,
+                color.g,
+                color.b);
+        }
+    }
+}
+
+fn set_raw_pixels<S : FbSlice + ?Sized>(fb : &mut S, bounds : &Bounds,
+                                        bytes : &[u8],
+                                        converter : &PixelConverter)
+{
+    let mut i = 0;
+    for y in 0..bounds.height() {
+        for x in 0..bounds.width() {
+            let byte_pos = i * converter.bytes_per_pixel();
+            let color = converter.to_bgrx(&bytes[byte_pos..]);
+            fb.set_pixel(
+                x + bounds.x,
+                y + bounds.y,
+                color.r,
+                color.g,
+                color.b
+            );
+            i += 1;
+        }
+    }
+}
+
+fn decode_in_pool(&mut (ref mut decoders, ref fb, ref cursor) : &mut State,
+                  converter : &PixelConverter,
+                  bounds : Bounds, method : EncodingMethod)
+    -> Result<(), MainError>
+{
+    decode(fb, cursor, converter, &mut decoders.zlib_decoder,
+           &mut decoders.jpeg_decoder, bounds, method)
+}
+
+fn decode(fb : &SharedFb, cursor : &SharedCursor, converter : &PixelConverter,
           zlib_decoder : &mut ZlibDecoder, jpeg_decoder : &mut JpegDecoder,
           bounds : Bounds,
           method : EncodingMethod) -> Result<(), MainError>
@@ -294,34 +476,37 @@ fn decode(fb : &SharedFb, cursor : &SharedCursor,
 
     match method {
         Fill(color) => {
-            for y in 0..bounds.height() {
-                for x in 0..bounds.width() {
-                    fb.set_pixel(
-                        x + bounds.x,
-                        y + bounds.y,
-                        color.r,
-                        color.g,
-                        color.b);
+            fill(&mut *fb, &bounds, &color);
+        },
+
+        Rre { background, subrects } => {
+            fill(&mut *fb, &bounds, &background);
+            for (subrect, color) in subrects {
+                if subrect.x < bounds.x || subrect.y < bounds.y
+                    || subrect.x + subrect.width() > bounds.x + bounds.width()
+                    || subrect.y + subrect.height()
+                        > bounds.y + bounds.height()
+                {
+                    return Err(MainError::new(String::from(
+                                "RRE subrectangle outside of the rectangle")));
                 }
+                fill(&mut *fb, &subrect, &color);
             }
         },
 
-        RawBgra(bytes) => {
-            let mut i = 0;
-            for y in 0..bounds.height() {
-                for x in 0..bounds.width() {
-                    let byte_pos = i * PIXEL_FORMAT_BYTES_PER_PIXEL;
-                    let bgra = &bytes[byte_pos..];
-                    fb.set_pixel(
-                        x + bounds.x,
-                        y + bounds.y,
-                        bgra[2],
-                        bgra[1],
-                        bgra[0]
-                    );
-                    i += 1;
-                }
+        Zlib(bytes) => {
+            let bytes = zlib_decoder.decode(&bytes[..])?;
+            if bytes.len() != bounds.width() * bounds.height()
+                * converter.bytes_per_pixel()
+            {
+                return Err(MainError::new(String::from(
+                            "Wrong number of pixels in Zlib rectangle")));
             }
+            set_raw_pixels(&mut *fb, &bounds, bytes, converter);
+        },
+
+        RawBgra(bytes) => {
+            set_raw_pixels(&mut *fb, &bounds, &bytes, converter);
         },
 
         CopyFilter(data) => {
@@ -342,13 +527,14 @@ fn decode(fb : &SharedFb, cursor : &SharedCursor,
 //                    });
 
                 for x in 0..bounds.width() {
+                    let color = converter.tpixel_to_bgrx(&data[i..]);
                     fb.set_pixel(
                         x + bounds.x,
                         y + bounds.y,
-                        data[i],
-                        data[i + 1],
-                        data[i + 2]);
-                    i += 3;
+                        color.r,
+                        color.g,
+                        color.b);
+                    i += converter.tpixel_len();
                 }
             }
         },
@@ -388,6 +574,47 @@ fn decode(fb : &SharedFb, cursor : &SharedCursor,
             }
         },
 
+        GradientFilter(ref data) if converter.tpixel_len() != 3 => {
+            if !converter.is_true_colour() {
+                return Err(MainError::new(String::from(
+                            "Gradient filter without true colour")));
+            }
+            let data = uncompress(zlib_decoder, data)?;
+            let colors = undo_gradient_filter_of_pixels(
+                data, bounds.width(), bounds.height(), converter)?;
+            let mut colors = colors.iter();
+            for y in 0..bounds.height() {
+                for x in 0..bounds.width() {
+                    let color = colors.next().unwrap();
+                    fb.set_pixel(
+                        x + bounds.x,
+                        y + bounds.y,
+                        color.r,
+                        color.g,
+                        color.b);
+                }
+            }
+        },
+
+        GradientFilter(data) => {
+            let data = uncompress(zlib_decoder, &data)?;
+            let rgb = undo_gradient_filter(data, bounds.width(),
+                                           bounds.height())?;
+
+            let mut i = 0;
+            for y in 0..bounds.height() {
+                for x in 0..bounds.width() {
+                    fb.set_pixel(
+                        x + bounds.x,
+                        y + bounds.y,
+                        rgb[i],
+                        rgb[i + 1],
+                        rgb[i + 2]);
+                    i += 3;
+                }
+            }
+        },
+
         Jpeg(data) => {
             let mut image = jpeg_decoder.decode(&data[..]).unwrap();
 
@@ -407,17 +634,68 @@ fn decode(fb : &SharedFb, cursor : &SharedCursor,
             }
         },
 
+        Png(data) => {
+            let image = png::decode(&data[..], bounds.width(),
+                                    bounds.height()).map_err(MainError::new)?;
+
+            let mut i = 0;
+            for y in 0..bounds.height() {
+                for x in 0..bounds.width() {
+                    fb.set_pixel(
+                        x + bounds.x,
+                        y + bounds.y,
+                        image.rgb[i],
+                        image.rgb[i + 1],
+                        image.rgb[i + 2]);
+                    i += 3;
+                }
+            }
+        },
+
+        TrleTile(tile) => {
+            trle::decode_tile(&tile, bounds.width(), bounds.height(),
+                              converter, |x, y, color| fb.set_pixel(
+                                  x + bounds.x,
+                                  y + bounds.y,
+                                  color.r,
+                                  color.g,
+                                  color.b))?;
+        },
+
+        HextileTile(tile) => {
+            hextile::decode_tile(&tile, bounds.width(), bounds.height(),
+                                 converter, |x, y, color| fb.set_pixel(
+                                     x + bounds.x,
+                                     y + bounds.y,
+                                     color.r,
+                                     color.g,
+                                     color.b))?;
+        },
+
+        Zrle(_) => {
+            panic!("ZRLE rectangles are split into tiles before decoding");
+        },
+
+        ZlibHex(_) => {
+            panic!("ZlibHex rectangles are split into tiles before decoding");
+        },
+
+        CopyRect { .. } => {
+            panic!("CopyRect is done by the DecodingMaster itself");
+        },
+
         CursorBgrx { pixels, bitmask } => {
             let mut rgba = Vec::with_capacity(pixels.len());
             let mut bits = BitBuffer::new(&bitmask[..]);
             let mut i = 0;
             for _ in 0..bounds.height() {
                 for _ in 0..bounds.width() {
-                    rgba.push(pixels[i + 2]);
-                    rgba.push(pixels[i + 1]);
-                    rgba.push(pixels[i + 0]);
+                    let color = converter.to_bgrx(&pixels[i..]);
+                    rgba.push(color.r);
+                    rgba.push(color.g);
+                    rgba.push(color.b);
                     rgba.push(bits.next() * 255);
-                    i += PIXEL_FORMAT_BYTES_PER_PIXEL;
+                    i += converter.bytes_per_pixel();
                 }
                 bits.next_byte();
             }
@@ -442,3 +720,131 @@ fn uncompress<'a, 'b : 'a>(decoder : &'b mut ZlibDecoder, data : &'a TightData)
         }
     })
 }
+
+//each component is sent as the difference to left + upper - upper left,
+//clamped to 0..255, with 0 for neighbours outside of the rectangle
+fn wrong_number_of_gradient_pixels() -> MainError {
+    MainError::new(String::from(
+            "Wrong number of pixels in Tight rectangle with gradient filter"))
+}
+
+fn undo_gradient_filter(data : &[u8], width : usize, height : usize)
+    -> Result<Vec<u8>, MainError>
+{
+    let stride = width * 3;
+    if data.len() != stride * height {
+        return Err(wrong_number_of_gradient_pixels());
+    }
+    let mut rgb = vec![0u8; stride * height];
+    for y in 0..height {
+        for x in 0..width {
+            for c in 0..3 {
+                let i = y * stride + x * 3 + c;
+                let left = if x > 0 { rgb[i - 3] as i32 } else { 0 };
+                let upper = if y > 0 { rgb[i - stride] as i32 } else { 0 };
+                let upper_left = if x > 0 && y > 0 {
+                    rgb[i - stride - 3] as i32
+                } else {
+                    0
+                };
+                let prediction = clamp_prediction(
+                    left + upper - upper_left, 255);
+                rgb[i] = data[i].wrapping_add(prediction as u8);
+            }
+        }
+    }
+    Ok(rgb)
+}
+
+fn clamp_prediction(prediction : i32, max : i32) -> i32 {
+    if prediction < 0 {
+        0
+    } else if prediction > max {
+        max
+    } else {
+        prediction
+    }
+}
+
+//pixels that are not sent as TPIXELs are predicted the same way, but
+//component by component up to their max
+fn undo_gradient_filter_of_pixels(data : &[u8], width : usize,
+                                  height : usize, converter : &PixelConverter)
+    -> Result<Vec<Bgrx>, MainError>
+{
+    let max = converter.max();
+    let bytes_per_pixel = converter.bytes_per_pixel();
+    if data.len() != width * height * bytes_per_pixel {
+        return Err(wrong_number_of_gradient_pixels());
+    }
+    let mut components = vec![[0u32; 3]; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            let difference =
+                converter.components(&data[i * bytes_per_pixel..]);
+            for c in 0..3 {
+                let left = if x > 0 { components[i - 1][c] as i32 } else { 0 };
+                let upper = if y > 0 {
+                    components[i - width][c] as i32
+                } else {
+                    0
+                };
+                let upper_left = if x > 0 && y > 0 {
+                    components[i - width - 1][c] as i32
+                } else {
+                    0
+                };
+                let prediction = clamp_prediction(
+                    left + upper - upper_left, max[c] as i32);
+                components[i][c] =
+                    (difference[c] + prediction as u32) & max[c];
+            }
+        }
+    }
+    Ok(components.into_iter().map(|c| converter.from_components(c))
+       .collect())
+}
+
+#[cfg(test)]
+mod the_gradient_filter {
+    use super::*;
+
+    #[test]
+    fn should_add_the_clamped_prediction_from_the_neighbours() {
+        let data = [10, 20, 30,   190, 30, 30,
+                    190, 60, 60,  6, 0, 0];
+        assert_eq!(undo_gradient_filter(&data, 2, 2).unwrap(),
+                   [10, 20, 30,   200, 50, 60,
+                    200, 80, 90,  5, 110, 120]);
+    }
+
+    #[test]
+    fn should_clamp_negative_predictions_to_zero() {
+        let data = [200, 200, 200,  56, 56, 56,
+                    56, 56, 56,     10, 20, 30];
+        assert_eq!(undo_gradient_filter(&data, 2, 2).unwrap(),
+                   [200, 200, 200,  0, 0, 0,
+                    0, 0, 0,        10, 20, 30]);
+    }
+
+    #[test]
+    fn should_return_an_error_for_truncated_data() {
+        let data = [10, 20, 30,   190, 30, 30];
+        assert!(undo_gradient_filter(&data, 2, 2).is_err());
+        let converter = PixelConverter::new(rfb::PixelFormat {
+            bits_per_pixel: 16,
+            depth: 16,
+            big_endian: false,
+            true_color: true,
+            red_max: 31,
+            green_max: 63,
+            blue_max: 31,
+            red_shift: 11,
+            green_shift: 5,
+            blue_shift: 0
+        });
+        assert!(undo_gradient_filter_of_pixels(&data, 2, 2, &converter)
+                .is_err());
+    }
+}
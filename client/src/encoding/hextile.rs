@@ -0,0 +1,258 @@
+// This file is part of flashvnc, a VNC client.
+// Copyright 2018 Patrick Plagwitz
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use ::{MainError,Bgrx};
+use protocol::rfb::{HextileTile,HextileEncoded,HextileSubrects,HextileRaw,
+                    ZlibHexTile};
+use protocol::parsing::ParameterizedPacket;
+use tight::ZlibDecoder;
+use super::pixel_converter::PixelConverter;
+
+use std::cell::RefCell;
+
+pub const HEXTILE_TILE_SIZE : usize = 16;
+
+fn error(reason : &str) -> MainError {
+    MainError::new(format!("Invalid Hextile tile: {}", reason))
+}
+
+fn fill<F>(position : (usize, usize), size : (usize, usize), color : &Bgrx,
+           width : usize, height : usize, set_pixel : &mut F)
+    -> Result<(), MainError>
+    where F : FnMut(usize, usize, &Bgrx)
+{
+    let (x, y) = position;
+    let (subrect_width, subrect_height) = size;
+    if x + subrect_width > width || y + subrect_height > height {
+        return Err(error("subrectangle outside of the tile"));
+    }
+    for y in y..y + subrect_height {
+        for x in x..x + subrect_width {
+            set_pixel(x, y, color);
+        }
+    }
+    Ok(())
+}
+
+//calls set_pixel with coordinates relative to the tile
+pub fn decode_tile<F>(tile : &HextileTile, width : usize, height : usize,
+                      converter : &PixelConverter, mut set_pixel : F)
+    -> Result<(), MainError>
+    where F : FnMut(usize, usize, &Bgrx)
+{
+    let encoded = match *tile {
+        HextileTile::Raw(ref raw) => {
+            for (i, pixel) in raw.pixels.chunks(converter.bytes_per_pixel())
+                .enumerate()
+            {
+                set_pixel(i % width, i / width, &converter.to_bgrx(pixel));
+            }
+            return Ok(());
+        },
+        HextileTile::Encoded(ref encoded) => encoded
+    };
+
+    let background = encoded.background.as_ref()
+        .expect("Hextile colours are resolved before decoding");
+    fill((0, 0), (width, height), &converter.to_bgrx(background),
+         width, height, &mut set_pixel)?;
+    match encoded.subrects {
+        Some(HextileSubrects::Foreground(ref subrects)) => {
+            let foreground = encoded.foreground.as_ref()
+                .expect("Hextile colours are resolved before decoding");
+            let color = converter.to_bgrx(foreground);
+            for subrect in subrects.iter() {
+                fill(subrect.position, subrect.size, &color,
+                     width, height, &mut set_pixel)?;
+            }
+        },
+        Some(HextileSubrects::Coloured(ref subrects)) => {
+            for subrect in subrects.iter() {
+                fill(subrect.position, subrect.size,
+                     &converter.to_bgrx(&subrect.color),
+                     width, height, &mut set_pixel)?;
+            }
+        },
+        None => {}
+    }
+    Ok(())
+}
+
+//tiles that leave out the background or foreground colour use the one of
+//the previous tile; this fills them in so that tiles can be decoded
+//independently
+pub fn resolve_hextile_colors(tile : HextileTile,
+                              background : &mut Option<Vec<u8>>,
+                              foreground : &mut Option<Vec<u8>>)
+    -> Result<HextileTile, MainError>
+{
+    let encoded = match tile {
+        HextileTile::Raw(raw) => return Ok(HextileTile::Raw(raw)),
+        HextileTile::Encoded(encoded) => encoded
+    };
+
+    if encoded.background.is_some() {
+        *background = encoded.background;
+    }
+    if encoded.foreground.is_some() {
+        *foreground = encoded.foreground;
+    }
+    if background.is_none() {
+        return Err(error("no background colour was specified"));
+    }
+    let needs_foreground = match encoded.subrects {
+        Some(HextileSubrects::Foreground(_)) => true,
+        _ => false
+    };
+    if needs_foreground && foreground.is_none() {
+        return Err(error("no foreground colour was specified"));
+    }
+    Ok(HextileTile::Encoded(HextileEncoded {
+        background: background.clone(),
+        foreground: foreground.clone(),
+        subrects: encoded.subrects
+    }))
+}
+
+//raw tiles of ZlibHex have a stream of their own
+pub fn inflate_zlib_hex_tile(tile : ZlibHexTile, width : usize,
+                             height : usize, pixel_len : usize,
+                             raw_stream : &mut ZlibDecoder,
+                             stream : &mut ZlibDecoder)
+    -> Result<HextileTile, MainError>
+{
+    match tile {
+        ZlibHexTile::Hextile(tile) => Ok(tile),
+        ZlibHexTile::ZlibRaw(data) => {
+            let pixels = raw_stream.decode(&data[..])?;
+            if pixels.len() != width * height * pixel_len {
+                return Err(error("wrong number of raw pixels"));
+            }
+            Ok(HextileTile::Raw(HextileRaw {
+                pixels: pixels.to_vec()
+            }))
+        },
+        ZlibHexTile::Zlib { flags, data } => {
+            let mut tile = vec![flags];
+            tile.extend_from_slice(stream.decode(&data[..])?);
+            let buffer = RefCell::new(Vec::new());
+            HextileTile::parse(&buffer, &tile[..],
+                               (pixel_len, width, height))
+                .map_err(|(err, position)| MainError::new(format!(
+                        "Error at position {} when parsing ZlibHex tile: {:?}",
+                        position, err)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod the_hextile_tile_decoder {
+    use super::*;
+    use protocol::rfb::{HextileRaw,HextileSubrect,HextileColouredSubrect};
+
+    fn decode(tile : &HextileTile, width : usize, height : usize)
+        -> Result<Vec<u8>, MainError>
+    {
+        let mut r = vec![0; width * height];
+        let converter = PixelConverter::new(::PIXEL_FORMAT);
+        decode_tile(tile, width, height, &converter, |x, y, color| {
+            r[y * width + x] = color.r;
+        })?;
+        Ok(r)
+    }
+
+    fn red(r : u8) -> Option<Vec<u8>> {
+        Some(vec![0, 0, r, 0])
+    }
+
+    #[test]
+    fn should_draw_subrects_over_the_background() {
+        let tile = HextileTile::Encoded(HextileEncoded {
+            background: red(1),
+            foreground: red(2),
+            subrects: Some(HextileSubrects::Foreground(vec![
+                HextileSubrect { position: (1, 0), size: (2, 2) }]))
+        });
+        assert_eq!(decode(&tile, 3, 3).unwrap(), [1, 2, 2,
+                                                  1, 2, 2,
+                                                  1, 1, 1]);
+        let tile = HextileTile::Encoded(HextileEncoded {
+            background: red(1),
+            foreground: None,
+            subrects: Some(HextileSubrects::Coloured(vec![
+                HextileColouredSubrect {
+                    color: vec![0, 0, 3, 0],
+                    position: (0, 1),
+                    size: (1, 1)
+                }]))
+        });
+        assert_eq!(decode(&tile, 2, 2).unwrap(), [1, 1,
+                                                  3, 1]);
+    }
+
+    #[test]
+    fn should_return_errors_for_subrects_outside_of_the_tile() {
+        let tile = HextileTile::Encoded(HextileEncoded {
+            background: red(1),
+            foreground: red(2),
+            subrects: Some(HextileSubrects::Foreground(vec![
+                HextileSubrect { position: (2, 0), size: (2, 1) }]))
+        });
+        assert!(decode(&tile, 3, 3).is_err());
+    }
+
+    #[test]
+    fn should_keep_the_colours_of_previous_tiles() {
+        let (mut background, mut foreground) = (None, None);
+        let first = HextileTile::Encoded(HextileEncoded {
+            background: red(1),
+            foreground: red(2),
+            subrects: None
+        });
+        resolve_hextile_colors(first, &mut background, &mut foreground)
+            .unwrap();
+        let raw = HextileTile::Raw(HextileRaw {
+            pixels: vec![0, 0, 9, 0]
+        });
+        resolve_hextile_colors(raw, &mut background, &mut foreground)
+            .unwrap();
+        let tile = HextileTile::Encoded(HextileEncoded {
+            background: None,
+            foreground: None,
+            subrects: Some(HextileSubrects::Foreground(vec![
+                HextileSubrect { position: (0, 0), size: (1, 1) }]))
+        });
+        let tile = resolve_hextile_colors(tile, &mut background,
+                                          &mut foreground).unwrap();
+        assert_eq!(decode(&tile, 2, 1).unwrap(), [2, 1]);
+    }
+
+    #[test]
+    fn should_require_the_first_tile_to_specify_its_colours() {
+        let tile = HextileTile::Encoded(HextileEncoded {
+            background: None,
+            foreground: None,
+            subrects: None
+        });
+        assert!(resolve_hextile_colors(tile, &mut None, &mut None).is_err());
+        let tile = HextileTile::Encoded(HextileEncoded {
+            background: red(1),
+            foreground: None,
+            subrects: Some(HextileSubrects::Foreground(vec![]))
+        });
+        assert!(resolve_hextile_colors(tile, &mut None, &mut None).is_err());
+    }
+}
@@ -15,6 +15,12 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 mod decoding_master;
+mod trle;
+mod hextile;
+mod pixel_converter;
 
 pub use self::decoding_master::{DecodingMaster,DecodingJob,EncodingMethod,
-                                TightData};
+                                TightData,Bounds};
+pub use self::trle::{TRLE_TILE_SIZE,resolve_reused_palette};
+pub use self::hextile::{HEXTILE_TILE_SIZE,resolve_hextile_colors};
+pub use self::pixel_converter::PixelConverter;
@@ -0,0 +1,274 @@
+// This file is part of flashvnc, a VNC client.
+// Copyright 2018 Patrick Plagwitz
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use ::Bgrx;
+use protocol::rfb;
+
+//turns pixels in the format that the server sends into Bgrx, using the
+//colour map if the format is not true colour
+#[derive(Clone)]
+pub struct PixelConverter {
+    format : rfb::PixelFormat,
+    //BGRX with 24 bit depth, which needs no conversion
+    native : bool,
+    colour_map : Vec<Bgrx>
+}
+impl PixelConverter {
+    pub fn new(format : rfb::PixelFormat) -> Self {
+        let native = format.bits_per_pixel == 32 && !format.big_endian
+            && format.true_color
+            && (format.red_max, format.green_max, format.blue_max)
+                == (255, 255, 255)
+            && (format.red_shift, format.green_shift, format.blue_shift)
+                == (16, 8, 0);
+        Self {
+            format: format,
+            native: native,
+            colour_map: Vec::new()
+        }
+    }
+
+    pub fn format(&self) -> &rfb::PixelFormat {
+        &self.format
+    }
+
+    pub fn is_true_colour(&self) -> bool {
+        self.format.true_color
+    }
+
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.format.bits_per_pixel as usize / 8
+    }
+
+    pub fn max(&self) -> [u32; 3] {
+        [self.format.red_max as u32,
+         self.format.green_max as u32,
+         self.format.blue_max as u32]
+    }
+
+    fn shifts(&self) -> [u32; 3] {
+        [self.format.red_shift as u32,
+         self.format.green_shift as u32,
+         self.format.blue_shift as u32]
+    }
+
+    //a TPIXEL of Tight is sent as three bytes R, G, B for 32 bit pixels
+    //of depth 24
+    pub fn tpixel_len(&self) -> usize {
+        let format = &self.format;
+        if format.true_color && format.bits_per_pixel == 32
+            && format.depth == 24
+            && self.max() == [255, 255, 255]
+        {
+            3
+        } else {
+            self.bytes_per_pixel()
+        }
+    }
+
+    //a CPIXEL of TRLE and ZRLE leaves out the unused byte of 32 bit pixels
+    pub fn cpixel_len(&self) -> usize {
+        if self.compact_bytes_at_front().is_some() {
+            3
+        } else {
+            self.bytes_per_pixel()
+        }
+    }
+
+    //where the byte that a CPIXEL leaves out belongs when it is put back
+    fn compact_bytes_at_front(&self) -> Option<bool> {
+        let format = &self.format;
+        if !format.true_color || format.bits_per_pixel != 32
+            || format.depth > 24
+        {
+            return None;
+        }
+        let mask = self.max().iter().zip(self.shifts().iter())
+            .fold(0u64, |mask, (&max, &shift)| mask | (max as u64) << shift);
+        if mask & 0xff00_0000 == 0 {
+            Some(format.big_endian)
+        } else if mask & 0x0000_00ff == 0 {
+            Some(!format.big_endian)
+        } else {
+            None
+        }
+    }
+
+    fn value(&self, pixel : &[u8]) -> u32 {
+        let bytes = &pixel[..self.bytes_per_pixel()];
+        if self.format.big_endian {
+            bytes.iter().fold(0, |value, &byte| value << 8 | byte as u32)
+        } else {
+            bytes.iter().rev().fold(0, |value, &byte| value << 8 | byte as u32)
+        }
+    }
+
+    //of true colour pixels, each up to its max
+    pub fn components(&self, pixel : &[u8]) -> [u32; 3] {
+        let value = self.value(pixel);
+        let (max, shifts) = (self.max(), self.shifts());
+        [value >> shifts[0] & max[0],
+         value >> shifts[1] & max[1],
+         value >> shifts[2] & max[2]]
+    }
+
+    pub fn from_components(&self, components : [u32; 3]) -> Bgrx {
+        let max = self.max();
+        let scale = |c : usize| if max[c] == 0 {
+            0
+        } else {
+            (components[c] * 255 / max[c]) as u8
+        };
+        Bgrx {
+            b: scale(2),
+            g: scale(1),
+            r: scale(0),
+            x: 0
+        }
+    }
+
+    pub fn to_bgrx(&self, pixel : &[u8]) -> Bgrx {
+        if self.native {
+            Bgrx::from_bytes(pixel)
+        } else if self.format.true_color {
+            self.from_components(self.components(pixel))
+        } else {
+            let index = self.value(pixel) as usize;
+            self.colour_map.get(index).cloned().unwrap_or(Bgrx {
+                b: 0,
+                g: 0,
+                r: 0,
+                x: 0
+            })
+        }
+    }
+
+    pub fn tpixel_to_bgrx(&self, tpixel : &[u8]) -> Bgrx {
+        if self.tpixel_len() == 3 {
+            Bgrx {
+                b: tpixel[2],
+                g: tpixel[1],
+                r: tpixel[0],
+                x: 0
+            }
+        } else {
+            self.to_bgrx(tpixel)
+        }
+    }
+
+    pub fn cpixel_to_bgrx(&self, cpixel : &[u8]) -> Bgrx {
+        match self.compact_bytes_at_front() {
+            Some(true) => self.to_bgrx(&[0, cpixel[0], cpixel[1], cpixel[2]]),
+            Some(false) => self.to_bgrx(&[cpixel[0], cpixel[1], cpixel[2], 0]),
+            None => self.to_bgrx(cpixel)
+        }
+    }
+
+    //the components of the colour map have 16 bits
+    pub fn set_colours(&mut self, first_colour : usize,
+                       colours : &[rfb::Colour])
+    {
+        let end = first_colour + colours.len();
+        if self.colour_map.len() < end {
+            self.colour_map.resize(end, Bgrx { b: 0, g: 0, r: 0, x: 0 });
+        }
+        for (i, colour) in colours.iter().enumerate() {
+            self.colour_map[first_colour + i] = Bgrx {
+                b: (colour.blue >> 8) as u8,
+                g: (colour.green >> 8) as u8,
+                r: (colour.red >> 8) as u8,
+                x: 0
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod the_pixel_converter {
+    use super::*;
+
+    fn true_colour(bits_per_pixel : u8, depth : u8, big_endian : bool,
+                   max : (u16, u16, u16), shifts : (u8, u8, u8))
+        -> PixelConverter
+    {
+        PixelConverter::new(rfb::PixelFormat {
+            bits_per_pixel: bits_per_pixel,
+            depth: depth,
+            big_endian: big_endian,
+            true_color: true,
+            red_max: max.0,
+            green_max: max.1,
+            blue_max: max.2,
+            red_shift: shifts.0,
+            green_shift: shifts.1,
+            blue_shift: shifts.2
+        })
+    }
+
+    fn rgb(color : Bgrx) -> [u8; 3] {
+        [color.r, color.g, color.b]
+    }
+
+    #[test]
+    fn should_scale_the_components_of_small_pixels_to_8_bits() {
+        let rgb565 = true_colour(16, 16, false, (31, 63, 31), (11, 5, 0));
+        assert_eq!(rgb(rgb565.to_bgrx(&[0x1f, 0xf8])), [255, 0, 255]);
+        assert_eq!(rgb(rgb565.to_bgrx(&[0xe0, 0x07])), [0, 255, 0]);
+        let rgb565_be = true_colour(16, 16, true, (31, 63, 31), (11, 5, 0));
+        assert_eq!(rgb(rgb565_be.to_bgrx(&[0xf8, 0x00])), [255, 0, 0]);
+        let bgr233 = true_colour(8, 8, false, (7, 7, 3), (0, 3, 6));
+        assert_eq!(rgb(bgr233.to_bgrx(&[0b11_000_111])), [255, 0, 255]);
+        assert_eq!(rgb(bgr233.to_bgrx(&[0b00_111_000])), [0, 255, 0]);
+    }
+
+    #[test]
+    fn should_look_up_pixels_in_the_colour_map_if_not_true_colour() {
+        let mut converter = PixelConverter::new(rfb::PixelFormat {
+            bits_per_pixel: 8,
+            depth: 8,
+            big_endian: false,
+            true_color: false,
+            red_max: 0,
+            green_max: 0,
+            blue_max: 0,
+            red_shift: 0,
+            green_shift: 0,
+            blue_shift: 0
+        });
+        converter.set_colours(2, &[rfb::Colour {
+            red: 0xffff,
+            green: 0x8000,
+            blue: 0x00ff
+        }]);
+        assert_eq!(rgb(converter.to_bgrx(&[2])), [255, 128, 0]);
+        assert_eq!(rgb(converter.to_bgrx(&[1])), [0, 0, 0]);
+        assert_eq!(rgb(converter.to_bgrx(&[200])), [0, 0, 0]);
+    }
+
+    #[test]
+    fn should_only_leave_out_bytes_of_32_bit_pixels() {
+        let bgrx = true_colour(32, 24, false, (255, 255, 255), (16, 8, 0));
+        assert_eq!((bgrx.tpixel_len(), bgrx.cpixel_len()), (3, 3));
+        assert_eq!(rgb(bgrx.tpixel_to_bgrx(&[1, 2, 3])), [1, 2, 3]);
+        assert_eq!(rgb(bgrx.cpixel_to_bgrx(&[1, 2, 3])), [3, 2, 1]);
+        let xrgb_be = true_colour(32, 24, true, (255, 255, 255), (16, 8, 0));
+        assert_eq!(rgb(xrgb_be.cpixel_to_bgrx(&[1, 2, 3])), [1, 2, 3]);
+        let rgbx_be = true_colour(32, 24, true, (255, 255, 255), (24, 16, 8));
+        assert_eq!(rgb(rgbx_be.cpixel_to_bgrx(&[1, 2, 3])), [1, 2, 3]);
+        let rgb565 = true_colour(16, 16, false, (31, 63, 31), (11, 5, 0));
+        assert_eq!((rgb565.tpixel_len(), rgb565.cpixel_len()), (2, 2));
+    }
+}
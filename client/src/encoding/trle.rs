@@ -0,0 +1,256 @@
+// This file is part of flashvnc, a VNC client.
+// Copyright 2018 Patrick Plagwitz
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use ::{MainError,Bgrx};
+use infrastructure::BitBuffer;
+use protocol::rfb::{self,TrleTile,TrlePackedPalette,TrlePaletteRle};
+use protocol::parsing::ParameterizedPacket;
+use super::decoding_master::Bounds;
+use super::pixel_converter::PixelConverter;
+
+use std::cell::RefCell;
+
+pub const TRLE_TILE_SIZE : usize = 16;
+pub const ZRLE_TILE_SIZE : usize = 64;
+
+fn error(reason : &str) -> MainError {
+    MainError::new(format!("Invalid TRLE tile: {}", reason))
+}
+
+fn palette_colors(palette : &[u8], converter : &PixelConverter) -> Vec<Bgrx> {
+    palette.chunks(converter.cpixel_len())
+        .map(|cpixel| converter.cpixel_to_bgrx(cpixel)).collect()
+}
+
+//calls set_pixel with coordinates relative to the tile
+pub fn decode_tile<F>(tile : &TrleTile, width : usize, height : usize,
+                      converter : &PixelConverter, mut set_pixel : F)
+    -> Result<(), MainError>
+    where F : FnMut(usize, usize, &Bgrx)
+{
+    use self::TrleTile::*;
+
+    match *tile {
+        Raw(ref raw) => {
+            for (i, cpixel) in raw.pixels.chunks(converter.cpixel_len())
+                .enumerate()
+            {
+                set_pixel(i % width, i / width,
+                          &converter.cpixel_to_bgrx(cpixel));
+            }
+        },
+        Solid(ref solid) => {
+            let color = converter.cpixel_to_bgrx(&solid.color);
+            for y in 0..height {
+                for x in 0..width {
+                    set_pixel(x, y, &color);
+                }
+            }
+        },
+        PackedPalette(ref packed) => {
+            let palette = palette_colors(&packed.palette, converter);
+            let bits_per_index =
+                rfb::packed_palette_bits_per_index(palette.len());
+            let mut bits = BitBuffer::new(&packed.indices[..]);
+            for y in 0..height {
+                bits.next_byte();
+                for x in 0..width {
+                    let index = bits.next_bits(bits_per_index) as usize;
+                    let color = palette.get(index).ok_or_else(
+                        || error("palette index out of range"))?;
+                    set_pixel(x, y, color);
+                }
+            }
+        },
+        PlainRle(ref rle) => {
+            let mut i = 0;
+            for run in rle.runs.iter() {
+                let color = converter.cpixel_to_bgrx(&run.color);
+                for j in i..i + run.length {
+                    set_pixel(j % width, j / width, &color);
+                }
+                i += run.length;
+            }
+        },
+        PaletteRle(ref rle) => {
+            let palette = palette_colors(&rle.palette, converter);
+            let mut i = 0;
+            for run in rle.runs.iter() {
+                let color = palette.get(run.index as usize).ok_or_else(
+                    || error("palette index out of range"))?;
+                for j in i..i + run.length {
+                    set_pixel(j % width, j / width, color);
+                }
+                i += run.length;
+            }
+        },
+        ReusedPackedPalette(_) | ReusedPaletteRle(_) => {
+            panic!("reused palettes are resolved before decoding");
+        }
+    }
+
+    Ok(())
+}
+
+//a TRLE tile can reuse the palette of the tile before it; this replaces
+//such tiles so that they can be decoded independently
+pub fn resolve_reused_palette(tile : TrleTile, palette : &mut Vec<u8>)
+    -> TrleTile
+{
+    use self::TrleTile::*;
+
+    match tile {
+        PackedPalette(packed) => {
+            *palette = packed.palette.clone();
+            PackedPalette(packed)
+        },
+        PaletteRle(rle) => {
+            *palette = rle.palette.clone();
+            PaletteRle(rle)
+        },
+        ReusedPackedPalette(packed) => PackedPalette(TrlePackedPalette {
+            palette: palette.clone(),
+            indices: packed.indices
+        }),
+        ReusedPaletteRle(rle) => PaletteRle(TrlePaletteRle {
+            palette: palette.clone(),
+            runs: rle.runs
+        }),
+        tile => tile
+    }
+}
+
+//ZRLE is TRLE with larger tiles inside the zlib data
+pub fn parse_zrle_tiles(mut data : &[u8], bounds : &Bounds,
+                        cpixel_len : usize)
+    -> Result<Vec<(Bounds, TrleTile)>, MainError>
+{
+    let buffer = RefCell::new(Vec::new());
+    bounds.tiles(ZRLE_TILE_SIZE).into_iter().map(|tile_bounds| {
+        let params = (cpixel_len, tile_bounds.width(),
+                      tile_bounds.height(), 0);
+        let tile = TrleTile::parse(&buffer, &mut data, params).map_err(
+            |(err, position)| MainError::new(format!(
+                    "Error at position {} when parsing ZRLE tile: {:?}",
+                    position, err)))?;
+        Ok((tile_bounds, tile))
+    }).collect()
+}
+
+#[cfg(test)]
+mod the_trle_tile_decoder {
+    use super::*;
+    use protocol::rfb::{TrleRaw,TrleSolid,TrlePlainRle,TrleRun,PaletteRun,
+                        TrleReusedPaletteRle};
+
+    fn decode(tile : &TrleTile, width : usize, height : usize)
+        -> Result<Vec<u8>, MainError>
+    {
+        let mut rgb = vec![0; width * height * 3];
+        let converter = PixelConverter::new(::PIXEL_FORMAT);
+        decode_tile(tile, width, height, &converter, |x, y, color| {
+            let i = (y * width + x) * 3;
+            rgb[i] = color.r;
+            rgb[i + 1] = color.g;
+            rgb[i + 2] = color.b;
+        })?;
+        Ok(rgb)
+    }
+
+    #[test]
+    fn should_decode_raw_and_solid_tiles() {
+        let raw = TrleTile::Raw(TrleRaw {
+            pixels: vec![3, 2, 1,  6, 5, 4]
+        });
+        assert_eq!(decode(&raw, 2, 1).unwrap(), [1, 2, 3,  4, 5, 6]);
+        let solid = TrleTile::Solid(TrleSolid {
+            color: vec![3, 2, 1]
+        });
+        assert_eq!(decode(&solid, 1, 2).unwrap(), [1, 2, 3,  1, 2, 3]);
+    }
+
+    #[test]
+    fn should_start_each_row_of_packed_indices_at_a_new_byte() {
+        let tile = TrleTile::PackedPalette(TrlePackedPalette {
+            palette: vec![0, 0, 10,  0, 0, 20,  0, 0, 30],
+            indices: vec![0b01_10_0000, 0b10_00_0000]
+        });
+        assert_eq!(decode(&tile, 2, 2).unwrap(),
+                   [20, 0, 0,  30, 0, 0,
+                    30, 0, 0,  10, 0, 0]);
+    }
+
+    #[test]
+    fn should_fill_runs_from_left_to_right_and_top_to_bottom() {
+        let tile = TrleTile::PlainRle(TrlePlainRle {
+            runs: vec![TrleRun { color: vec![0, 0, 1], length: 3 },
+                       TrleRun { color: vec![0, 0, 2], length: 1 }]
+        });
+        assert_eq!(decode(&tile, 2, 2).unwrap(),
+                   [1, 0, 0,  1, 0, 0,
+                    1, 0, 0,  2, 0, 0]);
+    }
+
+    #[test]
+    fn should_return_errors_for_indices_outside_of_the_palette() {
+        let tile = TrleTile::PackedPalette(TrlePackedPalette {
+            palette: vec![0, 0, 10,  0, 0, 20,  0, 0, 30],
+            indices: vec![0b11_00_0000, 0]
+        });
+        assert!(decode(&tile, 2, 2).is_err());
+        let tile = TrleTile::PaletteRle(TrlePaletteRle {
+            palette: vec![0, 0, 10],
+            runs: vec![PaletteRun { index: 1, length: 4 }]
+        });
+        assert!(decode(&tile, 2, 2).is_err());
+    }
+
+    #[test]
+    fn should_give_tiles_the_palette_of_the_previous_one() {
+        let mut palette = Vec::new();
+        resolve_reused_palette(TrleTile::PaletteRle(TrlePaletteRle {
+            palette: vec![0, 0, 10,  0, 0, 20],
+            runs: vec![PaletteRun { index: 0, length: 4 }]
+        }), &mut palette);
+        let tile = resolve_reused_palette(
+            TrleTile::ReusedPaletteRle(TrleReusedPaletteRle {
+                runs: vec![PaletteRun { index: 1, length: 4 }]
+            }), &mut palette);
+        assert_eq!(decode(&tile, 2, 2).unwrap(),
+                   [20, 0, 0,  20, 0, 0,
+                    20, 0, 0,  20, 0, 0]);
+    }
+
+    #[test]
+    fn should_parse_zrle_data_into_tiles_row_by_row() {
+        let data = [1, 0, 0, 1,  1, 0, 0, 2,  1, 0, 0, 3,  1, 0, 0, 4];
+        let tiles = parse_zrle_tiles(
+            &data, &Bounds::new(10, 20, ::FbSize::new(70, 65)), 3).unwrap();
+        let tiles : Vec<_> = tiles.iter().map(|&(ref bounds, ref tile)| {
+            (bounds.x, bounds.y, bounds.width(), bounds.height(),
+             decode(tile, 1, 1).unwrap()[0])
+        }).collect();
+        assert_eq!(tiles, [(10, 20, 64, 64, 1), (74, 20, 6, 64, 2),
+                           (10, 84, 64, 1, 3), (74, 84, 6, 1, 4)]);
+    }
+
+    #[test]
+    fn should_not_accept_reused_palettes_in_zrle() {
+        let data = [2,  0, 0, 1,  0, 0, 2,  0, 0, 0, 0, 0, 0, 0, 0,  127, 0];
+        assert!(parse_zrle_tiles(
+                &data, &Bounds::new(0, 0, ::FbSize::new(65, 1)), 3).is_err());
+    }
+}
@@ -15,7 +15,6 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use ::{FB_PIXEL_FORMAT};
-use protocol::rfb;
 use std::ptr;
 
 pub enum PixelFormat {
@@ -70,6 +69,7 @@ impl FbSize {
 }
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct Bgrx {
     pub b : u8,
     pub g : u8,
@@ -77,11 +77,12 @@ pub struct Bgrx {
     pub x : u8
 }
 impl Bgrx {
-    pub fn from_tpixel(tpixel : rfb::TPixel) -> Self {
+    //of a pixel in our pixel format
+    pub fn from_bytes(bytes : &[u8]) -> Self {
         Self {
-            r: tpixel.r,
-            g: tpixel.g,
-            b: tpixel.b,
+            b: bytes[0],
+            g: bytes[1],
+            r: bytes[2],
             x: 0
         }
     }
@@ -116,6 +117,29 @@ pub trait FbSlice {
             ptr::copy_nonoverlapping(line.as_ptr(), data, width);
         }
     }
+
+    //the source and the destination may overlap
+    fn copy_rect(&mut self, src_x : usize, src_y : usize,
+                 x : usize, y : usize, width : usize, height : usize)
+    {
+        assert!(src_x + width <= self.size().width);
+        assert!(src_y + height <= self.size().height);
+        assert!(x + width <= self.size().width);
+        assert!(y + height <= self.size().height);
+        let len = width * FB_PIXEL_FORMAT.bytes_per_pixel;
+        for i in 0..height {
+            //when copying downwards, rows have to be read from the bottom
+            //before they are overwritten
+            let row = if y > src_y { height - 1 - i } else { i };
+            let src_pos = self.byte_pos(src_x, src_y + row);
+            let pos = self.byte_pos(x, y + row);
+            let data = self.bytes();
+            unsafe {
+                ptr::copy(data.as_ptr().offset(src_pos as isize),
+                          data.as_mut_ptr().offset(pos as isize), len);
+            }
+        }
+    }
 }
 
 pub struct Framebuffer {
@@ -47,6 +47,15 @@ impl<'a> BitBuffer<'a> {
         ret
     }
 
+    //msb first, like single bits
+    pub fn next_bits(&mut self, n : usize) -> u8 {
+        let mut ret = 0;
+        for _ in 0..n {
+            ret = (ret << 1) | self.next();
+        }
+        ret
+    }
+
     pub fn next_byte(&mut self) {
         self.bit = 0;
     }
@@ -71,4 +80,13 @@ mod a_bit_buffer {
         assert_eq!(buf.next(), 1);
         assert_eq!(buf.next(), 0);
     }
+
+    #[test]
+    fn should_read_groups_of_bits_as_numbers() {
+        let mut buf = BitBuffer::new(&[0xb4, 0x70][..]);
+        assert_eq!(buf.next_bits(2), 2);
+        assert_eq!(buf.next_bits(2), 3);
+        assert_eq!(buf.next_bits(4), 4);
+        assert_eq!(buf.next_bits(4), 7);
+    }
 }
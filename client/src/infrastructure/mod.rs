@@ -15,6 +15,7 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 pub mod thread_pool;
+pub mod des;
 mod bit_buffer;
 pub use self::bit_buffer::BitBuffer;
 mod mode_lock;
@@ -47,6 +47,12 @@ impl<E : Value> Future<E> {
             has_waited: RefCell::new(false)
         }
     }
+    //for results that are already known
+    pub fn ready(result : JobResult<E>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        sender.send(result).unwrap();
+        Self::new(receiver)
+    }
     pub fn wait(&self) -> JobResult<E> {
         *self.has_waited.borrow_mut() = true;
         self.receiver.recv().unwrap()
@@ -151,6 +157,28 @@ fn worker_main<S : 'static>(
     }
 }
 
+fn spawn_with<S : 'static, F, E : Value>(job_sender : &mpsc::Sender<Box<Job<S>>>,
+                                         f : F) -> Future<E>
+    where F : FnOnce(&mut S) -> JobFuncResult<E> + Value
+{
+    let (sender, receiver) = mpsc::channel();
+    job_sender.send(Box::new(RespondingJob::new(f, sender))).unwrap();
+    Future::new(receiver)
+}
+
+//lets jobs running on other threads spawn jobs in a pool; the jobs must
+//be finished before the pool is dropped
+pub struct Spawner<S : 'static> {
+    job_sender : mpsc::Sender<Box<Job<S>>>
+}
+impl<S : 'static> Spawner<S> {
+    pub fn spawn_fn<F, E : Value>(&self, f : F) -> Future<E>
+        where F : FnOnce(&mut S) -> JobFuncResult<E> + Value
+    {
+        spawn_with(&self.job_sender, f)
+    }
+}
+
 pub struct ThreadPool<S : 'static> {
     threads : Vec<JoinHandle<()>>,
     job_sender : Option<mpsc::Sender<Box<Job<S>>>>,
@@ -191,10 +219,13 @@ impl<S : 'static> ThreadPool<S> {
     pub fn spawn_fn<F, E : Value>(&self, f : F) -> Future<E>
         where F : FnOnce(&mut S) -> JobFuncResult<E> + Value
     {
-        let (sender, receiver) = mpsc::channel();
-        self.job_sender.as_ref().unwrap().send(
-            Box::new(RespondingJob::new(f, sender))).unwrap();
-        Future::new(receiver)
+        spawn_with(self.job_sender.as_ref().unwrap(), f)
+    }
+
+    pub fn spawner(&self) -> Spawner<S> {
+        Spawner {
+            job_sender: self.job_sender.as_ref().unwrap().clone()
+        }
     }
 }
 impl<S : 'static> Drop for ThreadPool<S> {
@@ -210,7 +241,7 @@ impl<S : 'static> Drop for ThreadPool<S> {
 mod the_thread_pool {
     use super::*;
     use std::time::{Instant,Duration};
-    use std::thread::sleep;
+    use std::thread::{sleep,spawn};
     use std::sync::{Arc,Mutex};
 
     #[derive(Debug)]
@@ -297,4 +328,20 @@ mod the_thread_pool {
 
         assert_eq!(*n.lock().unwrap(), 6);
     }
+
+    #[test]
+    fn should_accept_jobs_from_other_threads_through_a_spawner() {
+        let pool = fixture_with_state(|| 5);
+        let spawner = pool.spawner();
+
+        let result = spawn(move || {
+            let f : Future<()> = spawner.spawn_fn(|x| {
+                *x += 1;
+                Ok(())
+            });
+            f.wait()
+        }).join().unwrap();
+
+        assert!(result.is_ok());
+    }
 }
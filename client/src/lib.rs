@@ -34,17 +34,21 @@ extern crate derivative;
 extern crate flate2;
 extern crate libc;
 extern crate sdl2;
+extern crate openssl;
 
 use std::io;
 use std::io::{BufReader,BufWriter,Read,Write};
 //use std::io::{Read,Write};
-use std::net::{TcpStream,UdpSocket};
+use std::net::{TcpListener,TcpStream,UdpSocket};
+use std::os::unix::net::UnixStream;
 use std::sync::mpsc;
 use std::collections::VecDeque;
+use std::path::PathBuf;
 
 pub mod infrastructure;
 use infrastructure::thread_pool::{ThreadPool,Future,FutureCollection};
 use infrastructure::ModeLock;
+use infrastructure::des::Des;
 
 mod presentation;
 use presentation::gtk as gtk_frontend;
@@ -58,12 +62,21 @@ pub use framebuffer::{Bgrx,FbSize,Framebuffer,FbSlice,FbAccess,
 pub type SharedFb = Arc<ModeLock<Framebuffer>>;
 
 mod encoding;
-use encoding::{DecodingJob,DecodingMaster,EncodingMethod,TightData};
+use encoding::{DecodingJob,DecodingMaster,EncodingMethod,TightData,Bounds,
+               TRLE_TILE_SIZE,resolve_reused_palette,
+               HEXTILE_TILE_SIZE,resolve_hextile_colors,PixelConverter};
 mod tight;
+mod transport;
+mod target;
+mod clipboard;
+use clipboard::Clipboard;
+use target::{Target,parse_target};
+use transport::{Transport,CommandTransport};
 
 use protocol::rfb;
 use protocol::parsing::io_input::SharedBuf;
-use protocol::parsing::Packet;
+use protocol::parsing::{Packet,ParameterizedPacket};
+use protocol::parsing::result::{ParseError,BytePosition};
 use std::cell::RefCell;
 use std::sync::{Arc,Mutex};
 use std::time::{Duration,Instant};
@@ -86,9 +99,6 @@ const PIXEL_FORMAT : rfb::PixelFormat = rfb::PixelFormat {
     green_shift: 8,
     blue_shift: 0
 };
-const PIXEL_FORMAT_BYTES_PER_PIXEL : usize = 
-    (PIXEL_FORMAT.bits_per_pixel as usize) / 8;
-const TPIXEL_SIZE : usize = 3;
 //[0, 0, 255, 0, 0, 0, 255, 0, 0, 0]
 //-> 0x00ff0000 -> red: 0x00ff & 0xff
 
@@ -101,11 +111,85 @@ const FB_PIXEL_FORMAT : FbPixelFormat = FbPixelFormat {
 
 //const CURSOR_BYTES_PER_PIXEL : usize = 4;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BellMode {
+    Audible,
+    Visual,
+    Off
+}
+
+//lower depths need less bandwidth
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelDepth {
+    TrueColour24,
+    //RGB565
+    TrueColour16,
+    //BGR233
+    TrueColour8,
+    //8 bit indices into the colour map of the server
+    ColourMap
+}
+impl PixelDepth {
+    fn pixel_format(&self) -> rfb::PixelFormat {
+        let (bits_per_pixel, true_color, max, shifts) = match *self {
+            PixelDepth::TrueColour24 => return PIXEL_FORMAT.clone(),
+            PixelDepth::TrueColour16 => (16, true, (31, 63, 31), (11, 5, 0)),
+            PixelDepth::TrueColour8 => (8, true, (7, 7, 3), (0, 3, 6)),
+            PixelDepth::ColourMap => (8, false, (0, 0, 0), (0, 0, 0))
+        };
+        rfb::PixelFormat {
+            bits_per_pixel: bits_per_pixel,
+            depth: bits_per_pixel,
+
+            big_endian: false,
+            true_color: true_color,
+
+            red_max: max.0,
+            green_max: max.1,
+            blue_max: max.2,
+
+            red_shift: shifts.0,
+            green_shift: shifts.1,
+            blue_shift: shifts.2
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct ConnectionConfig {
     pub host : String,
     pub port : u16,
     pub benchmark : bool,
-    pub throttle : bool
+    pub throttle : bool,
+    pub username : Option<String>,
+    pub password : Option<String>,
+    pub x509_ca_file : Option<PathBuf>,
+    //what the X.509 certificate of the server is checked against instead
+    //of the host
+    pub tls_server_name : Option<String>,
+    pub listen : Option<u16>,
+    pub proxy_command : Option<String>,
+    pub unix_socket : Option<PathBuf>,
+    pub reconnect : bool,
+    pub view_only : bool,
+    //whether other viewers may stay connected
+    pub shared : bool,
+    pub depth : PixelDepth,
+    pub bell : BellMode,
+    //offered to the server next to plain Tight
+    pub tight_png : bool
+}
+impl ConnectionConfig {
+    //there is no meaningful host name for Unix sockets or reverse
+    //connections
+    fn tls_server_name(&self) -> Option<&str> {
+        match self.tls_server_name {
+            Some(ref name) => Some(name),
+            None if self.unix_socket.is_some() || self.listen.is_some()
+                || self.host.is_empty() => None,
+            None => Some(&self.host)
+        }
+    }
 }
 
 pub enum EncodingQuality {
@@ -139,18 +223,33 @@ impl EncodingQuality {
         }
     }
 }
-fn get_rfb_encodings(encoding_quality : EncodingQuality) -> Vec<i32> {
+fn get_rfb_encodings(encoding_quality : EncodingQuality, tight_png : bool)
+    -> Vec<i32>
+{
     let mut encodings = vec![
+            rfb::ENCODING_COPY_RECT,
             rfb::ENCODING_TIGHT,
+            rfb::ENCODING_ZRLE,
+            rfb::ENCODING_TRLE,
+            rfb::ENCODING_ZLIB_HEX,
+            rfb::ENCODING_ZLIB,
+            rfb::ENCODING_HEXTILE,
+            rfb::ENCODING_CORRE,
+            rfb::ENCODING_RRE,
             rfb::ENCODING_RAW,
             
             rfb::ENCODING_CURSOR,
             rfb::ENCODING_EXTENDED_DESKTOP_SIZE,
+            rfb::ENCODING_DESKTOP_NAME,
 
             rfb::ENCODING_LAST_RECT,
             rfb::ENCODING_CONTINUOUS_UPDATES,
-            rfb::ENCODING_FENCE
+            rfb::ENCODING_FENCE,
+            rfb::ENCODING_EXTENDED_CLIPBOARD
     ];
+    if tight_png {
+        encodings.insert(2, rfb::ENCODING_TIGHT_PNG);
+    }
     encodings.append(&mut encoding_quality.get_rfb_encodings());
     encodings
 }
@@ -171,7 +270,10 @@ pub enum GuiEvent {
         down : bool
     },
     SetEncodingQuality(EncodingQuality),
-    Resized(FbSize)
+    Resized(FbSize),
+    SetViewOnly(bool),
+    //the local clipboard has new text
+    ClipboardText(String)
 }
 
 #[derive(Clone, Copy)]
@@ -276,7 +378,10 @@ pub enum ProtocolEvent {
     ChangeDisplaySize(FbSize),
     UpdateFramebuffer(Vec<u8>, FbSize),
     UpdateCursor(Vec<u8>, CursorSize, Hotspot),
-    SetTitle(String)
+    SetTitle(String),
+    SetClipboardText(String),
+    //audible or visual, as configured
+    Bell
 }
 pub trait View {
     type Output : ViewOutput;
@@ -287,6 +392,12 @@ pub trait View {
     fn set_title(&self, title : String) {
         self.get_output().handle_event(ProtocolEvent::SetTitle(title));
     }
+    fn set_clipboard_text(&self, text : String) {
+        self.get_output().handle_event(ProtocolEvent::SetClipboardText(text));
+    }
+    fn ring_bell(&self) {
+        self.get_output().handle_event(ProtocolEvent::Bell);
+    }
 
     fn get_output(&self) -> &Self::Output;
     fn get_events(&mut self) -> mpsc::Receiver<GuiEvent>;
@@ -423,10 +534,36 @@ impl ThrottleController {
     }
 }
 
-pub struct MainError(pub String);
+#[derive(Debug)]
+pub struct MainError(pub String, pub ErrorKind);
+
+//with --reconnect, only transport failures lead to another attempt; errors
+//of the handshake or the protocol would only repeat themselves
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum ErrorKind {
+    Transport,
+    Other
+}
+
+const CONNECTION_BROKEN : &'static str = "Connection to server broken";
+const CANT_WRITE_PACKET : &'static str =
+    "Can't write packet, connection to server broken";
+impl MainError {
+    pub fn new(message : String) -> Self {
+        MainError(message, ErrorKind::Other)
+    }
+    fn transport(message : String) -> Self {
+        MainError(message, ErrorKind::Transport)
+    }
+}
 impl From<io::Error> for MainError {
     fn from(err : io::Error) -> Self {
-        MainError(format!("I/O error: {:?}", err))
+        MainError::transport(format!("I/O error: {:?}", err))
+    }
+}
+impl From<openssl::error::ErrorStack> for MainError {
+    fn from(err : openssl::error::ErrorStack) -> Self {
+        MainError::new(format!("OpenSSL error: {}", err))
     }
 }
 impl<E> From<infrastructure::thread_pool::Error<E>> for MainError 
@@ -435,7 +572,7 @@ impl<E> From<infrastructure::thread_pool::Error<E>> for MainError
     fn from(err : infrastructure::thread_pool::Error<E>) -> Self {
         use infrastructure::thread_pool::Error::*;
         match err {
-            Panic => MainError(String::from("panic while decoding")),
+            Panic => MainError::new(String::from("panic while decoding")),
             Value(e) => MainError::from(e)
         }
     }
@@ -447,7 +584,21 @@ impl<E> From<Vec<E>> for MainError
         let ret = String::from("multiple errors: ");
         let strings : Vec<_> = errors.drain(..).map(|e| MainError::from(e).0)
             .collect();
-        MainError(ret + &strings.join(",\n"))
+        MainError::new(ret + &strings.join(",\n"))
+    }
+}
+
+fn parse_error((err, position) : (ParseError, BytePosition),
+               packet_name : &str) -> MainError
+{
+    if err.is_eof() {
+        return MainError::transport(String::from(CONNECTION_BROKEN));
+    }
+    match err {
+        ParseError::IoError(io_error) => MainError::from(io_error),
+        err => MainError::new(format!(
+                "Error at position {} when parsing packet {}: {:?}",
+                position, packet_name, err))
     }
 }
 
@@ -456,17 +607,16 @@ fn parse_packet<T, I>(buffer : &SharedBuf, input : I)
     where T : Packet,
           I : io::Read
 {
-    let ret = T::parse(buffer, input);
-    match ret {
-        Ok(x) => Ok(x),
-        Err((err, position)) => if err.is_eof() {
-            Err(MainError(String::from("Connection to server broken")))
-        } else {
-            Err(MainError(format!(
-                        "Error at position {} when parsing packet {}: {:?}",
-                        position, T::name(), err)))
-        }
-    }
+    T::parse(buffer, input).map_err(|err| parse_error(err, T::name()))
+}
+
+fn parse_parameterized_packet<T, I>(buffer : &SharedBuf, input : I,
+                                    params : T::Params)
+    -> Result<T, MainError>
+    where T : ParameterizedPacket,
+          I : io::Read
+{
+    T::parse(buffer, input, params).map_err(|err| parse_error(err, T::name()))
 }
 
 fn write_packet<T, O>(packet : T, output : O)
@@ -477,40 +627,153 @@ fn write_packet<T, O>(packet : T, output : O)
     match packet.write(output) {
         Ok(()) => Ok(()),
         Err(err) => if err.is_io_error() {
-            Err(MainError(String::from(
-                "Can't write packet, connection to server broken")))
+            Err(MainError::transport(String::from(CANT_WRITE_PACKET)))
         } else {
-            Err(MainError(format!("Can't write packet {}: {:?}",
-                                  T::name(), err)))
+            Err(MainError::new(format!("Can't write packet {}: {:?}",
+                                       T::name(), err)))
         }
     }
 }
 
+fn connect(config : &ConnectionConfig) -> Result<Box<Transport>, MainError> {
+    if let Some(ref command) = config.proxy_command {
+        let transport = CommandTransport::spawn(command).map_err(|err|
+            MainError::transport(format!("Could not run proxy command {}: {}",
+                                         command, err)))?;
+        return Ok(Box::new(transport));
+    }
+    if let Some(ref path) = config.unix_socket {
+        let socket = UnixStream::connect(path).map_err(|err|
+            MainError::transport(format!("Could not connect to {}: {}",
+                                         path.display(), err)))?;
+        return Ok(Box::new(socket));
+    }
+
+    match TcpStream::connect((&config.host[..], config.port)) {
+        Ok(s) => Ok(Box::new(s)),
+        Err(err) => {
+            //TODO implement From and then Display for MainError
+            Err(MainError::transport(format!(
+                        "Could not connect to {} port {}: {}",
+                        config.host, config.port, err)))
+        }
+    }
+}
+
+const INITIAL_RECONNECT_DELAY_MS : u64 = 500;
+const MAX_RECONNECT_DELAY_MS : u64 = 30000;
+
 pub fn socket_thread_main<V : View>(
     config : ConnectionConfig,
-    view : V) -> Result<(), MainError>
+    mut view : V) -> Result<(), MainError>
 {
-    //TODO handle differently (re-connect)?
-    let host_and_port = format!("{}:{}", config.host, config.port);
-    let socket = match TcpStream::connect(&host_and_port) {
-        Ok(s) => s,
-        Err(err) => {
-            //TODO implement From and then Display for MainError
-            return Err(MainError(format!("Could not connect to {}: {}", 
-                                         host_and_port, err)));
+    if let Some(port) = config.listen {
+        return listen_for_servers(config, port, view);
+    }
+
+    let write_end_slot = forward_gui_events(view.get_events(),
+                                            config.view_only);
+    let mut reconnect_delay = INITIAL_RECONNECT_DELAY_MS;
+    loop {
+        //TODO benchmark BufferedReader
+        let result = connect(&config).and_then(|transport|
+            RfbConnection::new(config.clone(), transport, &view,
+                               write_end_slot.clone()).handle());
+        //the write end only exists once the handshake is done
+        let was_established = write_end_slot.lock().unwrap().take().is_some();
+
+        let error_message = match result {
+            Ok(()) => return Ok(()),
+            Err(MainError(error_message, ErrorKind::Transport))
+                if config.reconnect => error_message,
+            Err(err) => return Err(err)
+        };
+        if was_established {
+            reconnect_delay = INITIAL_RECONNECT_DELAY_MS;
         }
-    };
+        eprintln!("Connection to server lost: {}", error_message);
+        eprintln!("Reconnecting in {} ms", reconnect_delay);
+        view.set_title(String::from("reconnecting…"));
+
+        std::thread::sleep(Duration::from_millis(reconnect_delay));
+        reconnect_delay = std::cmp::min(reconnect_delay * 2,
+                                        MAX_RECONNECT_DELAY_MS);
+    }
+}
 
-    //TODO benchmark BufferedReader
-    handle_connection(config, socket, view)
+//reverse connection: the servers connect to us, one after the other
+fn listen_for_servers<V : View>(
+    config : ConnectionConfig,
+    port : u16,
+    mut view : V) -> Result<(), MainError>
+{
+    //"::" also accepts IPv4 connections unless the system is set to
+    //IPv6-only sockets; without IPv6 support fall back to IPv4 only
+    let listener = TcpListener::bind(("::", port))
+        .or_else(|_| TcpListener::bind(("0.0.0.0", port)))
+        .map_err(|err| MainError::new(format!(
+            "Could not listen on port {}: {}", port, err)))?;
+    let write_end_slot = forward_gui_events(view.get_events(),
+                                            config.view_only);
+
+    view.set_title(format!("listening on port {}", port));
+    for socket in listener.incoming() {
+        let socket = match socket {
+            Ok(socket) => socket,
+            Err(err) => {
+                eprintln!("Could not accept a connection: {}", err);
+                continue;
+            }
+        };
+        let result = RfbConnection::new(config.clone(), Box::new(socket),
+                                        &view, write_end_slot.clone())
+            .handle();
+        if let Err(MainError(error_message, _)) = result {
+            eprintln!("Connection to server ended: {}", error_message);
+        }
+        *write_end_slot.lock().unwrap() = None;
+        view.set_title(format!("listening on port {}", port));
+    }
+    Ok(())
 }
 
 pub fn handle_connection<V : View>(
     config : ConnectionConfig,
     socket : TcpStream,
-    view : V) -> Result<(), MainError>
+    mut view : V) -> Result<(), MainError>
+{
+    let write_end_slot = forward_gui_events(view.get_events(),
+                                            config.view_only);
+    RfbConnection::new(config, Box::new(socket), &view, write_end_slot)
+        .handle()
+}
+
+//the write end of the current connection, if there is one
+type WriteEndSlot = Arc<Mutex<Option<mpsc::Sender<RfbWriteEvent>>>>;
+
+fn forward_gui_events(gui_events : mpsc::Receiver<GuiEvent>,
+                      view_only : bool) -> WriteEndSlot
 {
-    RfbConnection::new(config, socket, view).handle()
+    let slot : WriteEndSlot = Arc::new(Mutex::new(None));
+    let slot_clone = slot.clone();
+    std::thread::spawn(move || {
+        let mut view_only = view_only;
+        while let Ok(event) = gui_events.recv() {
+            match event {
+                //the write end releases what is still pressed
+                GuiEvent::SetViewOnly(on) => view_only = on,
+                GuiEvent::Pointer { .. }
+                    | GuiEvent::RelativePointer { .. }
+                    | GuiEvent::Keyboard { .. }
+                    | GuiEvent::ClipboardText(_) if view_only => continue,
+                _ => { }
+            }
+            if let Some(ref sender) = *slot_clone.lock().unwrap() {
+                sender.send(RfbWriteEvent::GuiEvent(event)).unwrap_or(());
+            }
+        }
+    });
+    slot
 }
 
 enum RfbWriteEvent {
@@ -531,12 +794,17 @@ enum RfbWriteEvent {
         flags : u32,
         payload : Vec<u8>
     },
+    //the clipboard text that came from the server
+    ServerClipboardText(String),
+    //all but Provide, which is handled when reading
+    ExtendedClipboard(rfb::ExtendedClipboard),
     Heartbeat
 }
 struct RfbWriteEnd {
-    socket : BufWriter<TcpStream>,
+    socket : BufWriter<Box<Transport>>,
     input : mpsc::Receiver<RfbWriteEvent>,
-    mouse_server : UdpSocket
+    mouse_server : Option<UdpSocket>,
+    tight_png : bool
 }
 impl RfbWriteEnd {
     fn write_packet<T>(&mut self, packet : T) -> Result<(), MainError>
@@ -551,19 +819,19 @@ impl RfbWriteEnd {
 
         let mut virtual_cursor_difference = CursorDifference::new();
         let mut previous_mouse_state = 0;
+        //what the server has been told is pressed
+        let mut pressed_keys : Vec<u32> = Vec::new();
+        let mut pointer = (0, 0, 0);
         let mut screen_layout : Vec<rfb::Screen> = Vec::new();
         let mut set_desktop_size_allowed = false;
+        let mut clipboard = Clipboard::new();
 
         while let Ok(event) = self.input.recv() {
             match event {
                 GuiEvent(Gui::Pointer { state, x, y }) => {
                     //eprintln!("button state: {:x}, x: {}, y: {}", state, x, y);
-                    self.write_packet(rfb::ClientToServer::PointerEvent(
-                            rfb::PointerEvent {
-                                mask: state,
-                                x: x as u16, //TODO clamp
-                                y: y as u16
-                            }))?;
+                    pointer = (state, x as u16, y as u16); //TODO clamp
+                    self.send_pointer_event(pointer)?;
                 },
                 GuiEvent(Gui::RelativePointer { state, dx, dy }) => {
                     virtual_cursor_difference.add(dx, dy);
@@ -572,25 +840,20 @@ impl RfbWriteEnd {
                     let state_changed = previous_mouse_state != state;
                     previous_mouse_state = state;
                     if int_dx != 0 || int_dy != 0 || state_changed {
-                        let mut message = Vec::new();
 //                        if state != 0 {
 //                            eprintln!("{:?} sending relative mouse message {} {}", 
 //                                    Instant::now(), int_dx, int_dy);
 //                        }
-                        protocol::VirtualMouseServerMessage {
-                            button_mask: state,
-                            dx: int_dx as i16,
-                            dy: int_dy as i16
-                        }.write(&mut message).unwrap();
-                        self.mouse_server.send(&message[..])?;
+                        self.send_relative_pointer_event(
+                            state, int_dx as i16, int_dy as i16)?;
                     }
                 },
                 GuiEvent(Gui::Keyboard { key, down }) => {
-                    self.write_packet(rfb::ClientToServer::KeyEvent(
-                            rfb::KeyEvent {
-                                down: down,
-                                key: key
-                            }))?;
+                    pressed_keys.retain(|&pressed| pressed != key);
+                    if down {
+                        pressed_keys.push(key);
+                    }
+                    self.send_key_event(key, down)?;
                 },
                 GuiEvent(Gui::Resized(new_size)) => {
 //                        if new_size != self.framebuffer.size()
@@ -608,6 +871,26 @@ impl RfbWriteEnd {
                 GuiEvent(Gui::SetEncodingQuality(new_quality)) => {
                     self.send_set_encodings(new_quality)?;
                 },
+                GuiEvent(Gui::ClipboardText(text)) => {
+                    if let Some(text) = clipboard.local_text_changed(text) {
+                        self.send_cut_text(text)?;
+                    }
+                },
+                //no more input follows, so nothing may stay pressed
+                GuiEvent(Gui::SetViewOnly(true)) => {
+                    for key in pressed_keys.drain(..) {
+                        self.send_key_event(key, false)?;
+                    }
+                    if pointer.0 != 0 {
+                        pointer.0 = 0;
+                        self.send_pointer_event(pointer)?;
+                    }
+                    if previous_mouse_state != 0 {
+                        previous_mouse_state = 0;
+                        self.send_relative_pointer_event(0, 0, 0)?;
+                    }
+                },
+                GuiEvent(Gui::SetViewOnly(false)) => { },
                 SetScreenLayout(layout) => {
                     screen_layout = layout
                 },
@@ -645,6 +928,16 @@ impl RfbWriteEnd {
                     self.write_packet(rfb::ClientToServer
                                       ::Fence(message))?;
                 },
+                ServerClipboardText(text) => {
+                    clipboard.server_text_changed(text);
+                },
+                ExtendedClipboard(message) => {
+                    if let Some(text) = clipboard.handle_server_message(
+                        message)
+                    {
+                        self.send_cut_text(text)?;
+                    }
+                },
                 Heartbeat => { }
             }
             self.socket.flush()?;
@@ -652,55 +945,111 @@ impl RfbWriteEnd {
         Ok(())
     }
 
+    fn send_key_event(&mut self, key : u32, down : bool)
+        -> Result<(), MainError>
+    {
+        self.write_packet(rfb::ClientToServer::KeyEvent(
+                rfb::KeyEvent {
+                    down: down,
+                    key: key
+                }))
+    }
+
+    fn send_pointer_event(&mut self, (mask, x, y) : (u8, u16, u16))
+        -> Result<(), MainError>
+    {
+        self.write_packet(rfb::ClientToServer::PointerEvent(
+                rfb::PointerEvent {
+                    mask: mask,
+                    x: x,
+                    y: y
+                }))
+    }
+
+    fn send_relative_pointer_event(&self, button_mask : u8, dx : i16, dy : i16)
+        -> Result<(), MainError>
+    {
+        let mut message = Vec::new();
+        protocol::VirtualMouseServerMessage {
+            button_mask: button_mask,
+            dx: dx,
+            dy: dy
+        }.write(&mut message).unwrap();
+        if let Some(ref mouse_server) = self.mouse_server {
+            mouse_server.send(&message[..])?;
+        }
+        Ok(())
+    }
+
+    fn send_cut_text(&mut self, text : rfb::CutText) -> Result<(), MainError> {
+        self.write_packet(rfb::ClientToServer::ClientCutText(
+                rfb::ClientCutText {
+                    text: text
+                }))
+    }
+
     fn send_set_encodings(&mut self, encoding_quality : EncodingQuality)
         ->  Result<(), MainError>
     {
         self.write_packet(rfb::ClientToServer::SetEncodings(rfb::SetEncodings {
-            encodings: get_rfb_encodings(encoding_quality)
+            encodings: get_rfb_encodings(encoding_quality, self.tight_png)
         }))
     }
 
 }
 
-fn disable_nagles_algo(socket : &TcpStream) -> io::Result<()> {
-    use std::os::unix::io::AsRawFd;
-
-    let one : libc::c_int = 1;
-    let socket_fd = socket.as_raw_fd();
-    let ret;
-    unsafe {
-        ret = libc::setsockopt(
-            socket_fd, libc::SOL_TCP, libc::TCP_NODELAY, 
-            &one as *const libc::c_int as *const libc::c_void, 
-            std::mem::size_of::<libc::c_int>() as libc::socklen_t);
+//the password is used as a DES key, but with the bits of every byte mirrored
+fn encrypt_vnc_auth_challenge(password : &str, challenge : &[u8]) -> Vec<u8> {
+    let mut key = [0u8; 8];
+    for (key_byte, &password_byte) in key.iter_mut()
+        .zip(password.as_bytes().iter())
+    {
+        let mut mirrored = 0;
+        for bit in 0..8 {
+            mirrored |= ((password_byte >> bit) & 1) << (7 - bit);
+        }
+        *key_byte = mirrored;
     }
-    if ret != 0 {
-        Err(io::Error::last_os_error())
-    } else {
-        Ok(())
+    let des = Des::new(&key);
+
+    let mut response = Vec::with_capacity(challenge.len());
+    for chunk in challenge.chunks(8) {
+        let mut block = [0u8; 8];
+        block.copy_from_slice(chunk);
+        response.extend_from_slice(&des.encrypt_block(&block));
     }
+    response
 }
 
-struct RfbConnection<V : View> {
+struct RfbConnection<'a, V : View + 'a> {
     config : ConnectionConfig,
-    socket : BufReader<TcpStream>,
-    view : V,
+    socket : BufReader<Box<Transport>>,
+    version : rfb::Version,
+    view : &'a V,
     buffer : SharedBuf,
     framebuffer : SharedFb,
-    write_end_sender : Option<mpsc::Sender<RfbWriteEvent>>
+    write_end_sender : Option<mpsc::Sender<RfbWriteEvent>>,
+    write_end_slot : WriteEndSlot,
+    //holds the colour map
+    pixel_converter : PixelConverter
 }
-impl<V : View> RfbConnection<V> {
-    fn new(config : ConnectionConfig, socket : TcpStream, view : V) 
+impl<'a, V : View + 'a> RfbConnection<'a, V> {
+    fn new(config : ConnectionConfig, socket : Box<Transport>, view : &'a V,
+           write_end_slot : WriteEndSlot) 
         -> Self
     {
         let buffer = RefCell::new(Vec::new());
+        let pixel_converter = PixelConverter::new(config.depth.pixel_format());
         Self {
             config: config,
             socket: BufReader::new(socket),
+            version: rfb::Version::V3_8,
             view: view,
             buffer: buffer,
             framebuffer: Arc::new(ModeLock::new(Framebuffer::new())),
-            write_end_sender: None
+            write_end_sender: None,
+            write_end_slot: write_end_slot,
+            pixel_converter: pixel_converter
         }
     }
 
@@ -709,6 +1058,12 @@ impl<V : View> RfbConnection<V> {
     {
         parse_packet(&self.buffer, &mut self.socket)
     }
+    fn parse_parameterized_packet<T>(&mut self, params : T::Params)
+        -> Result<T, MainError>
+        where T : ParameterizedPacket
+    {
+        parse_parameterized_packet(&self.buffer, &mut self.socket, params)
+    }
     fn write_packet<T>(&mut self, packet : T) -> Result<(), MainError>
         where T : Packet
     {
@@ -723,6 +1078,40 @@ impl<V : View> RfbConnection<V> {
         self.socket.read_exact(&mut bytes[..])?;
         Ok(bytes)
     }
+    fn read_pixel(&mut self) -> Result<Bgrx, MainError> {
+        let len = self.pixel_converter.bytes_per_pixel();
+        let pixel = self.read_bytes(len)?;
+        Ok(self.pixel_converter.to_bgrx(&pixel))
+    }
+    fn read_tpixel(&mut self) -> Result<Bgrx, MainError> {
+        let len = self.pixel_converter.tpixel_len();
+        let tpixel = self.read_bytes(len)?;
+        Ok(self.pixel_converter.tpixel_to_bgrx(&tpixel))
+    }
+    //RRE and CoRRE only differ in the sizes of the fields of their subrects
+    fn read_rre_subrects<F>(&mut self, rectangle : &rfb::Rectangle,
+                            no_of_subrects : usize, read_subrect : F)
+        -> Result<EncodingMethod, MainError>
+        where F : Fn(&mut Self, usize)
+            -> Result<(usize, usize, usize, usize, Vec<u8>), MainError>
+    {
+        let background = self.read_pixel()?;
+        let pixel_len = self.pixel_converter.bytes_per_pixel();
+        //no capacity up front, the number comes from the server
+        let mut subrects = Vec::new();
+        for _ in 0..no_of_subrects {
+            let (x, y, width, height, color) = read_subrect(self, pixel_len)?;
+            subrects.push((
+                Bounds::new(rectangle.x + x, rectangle.y + y,
+                            FbSize::new(width, height)),
+                self.pixel_converter.to_bgrx(&color)));
+        }
+        Ok(EncodingMethod::Rre {
+            background: background,
+            subrects: subrects
+        })
+    }
+    //less than 12 bytes of data are sent without compression and length
     fn read_zlib_data(&mut self, stream_no : usize, uncompressed_size : usize)
         -> Result<TightData, MainError>
     {
@@ -737,6 +1126,15 @@ impl<V : View> RfbConnection<V> {
         })
     }
 
+    fn read_copy_filter_data(&mut self, rectangle : &rfb::Rectangle,
+                             stream_no : usize)
+        -> Result<TightData, MainError>
+    {
+        let uncompressed_size = rectangle.width * rectangle.height
+            * self.pixel_converter.tpixel_len();
+        self.read_zlib_data(stream_no, uncompressed_size)
+    }
+
     fn resize_fb(&mut self, new_size : FbSize) {
         self.view.change_display_size_to(new_size);
         self.framebuffer.lock(FbAccess::Resizing).resize(new_size);
@@ -767,15 +1165,16 @@ impl<V : View> RfbConnection<V> {
         
         self.view.set_title(server_init.name.clone());
         self.write_packet(rfb::ClientToServer::SetEncodings(rfb::SetEncodings {
-            encodings: get_rfb_encodings(EncodingQuality::LossyHigh)
+            encodings: get_rfb_encodings(EncodingQuality::LossyHigh,
+                                         self.config.tight_png)
         }))?;
         self.write_packet(rfb::ClientToServer::SetPixelFormat(
                 rfb::SetPixelFormat {
-                    format: PIXEL_FORMAT.clone()
+                    format: self.pixel_converter.format().clone()
                 }
         ))?;
 
-        disable_nagles_algo(self.socket.get_ref())?;
+        self.socket.get_ref().disable_nagles_algo()?;
 
         self.handle_main_part(server_init)
         //TODO call exit on view instead
@@ -788,40 +1187,256 @@ impl<V : View> RfbConnection<V> {
     }
 
     fn setup(&mut self) -> Result<rfb::ServerInit, MainError> {
-        let _server_address = self.socket.get_ref().peer_addr().unwrap();
+        let server_version = self.parse_packet::<rfb::ProtocolVersion>()?;
+        self.version = match server_version.negotiate() {
+            Some(version) => version,
+            None => return Err(MainError::new(format!(
+                    "Unsupported RFB version of server: {}", 
+                    server_version.string.trim())))
+        };
+        self.write_packet(rfb::ProtocolVersion::new(self.version))?;
+
+        let sec_type = if self.version == rfb::Version::V3_3 {
+            self.receive_security_type()?
+        } else {
+            self.choose_security_type()?
+        };
+        if sec_type == rfb::SEC_TYPE_VNC {
+            self.authenticate_with_vnc_auth()?;
+        } else if sec_type == rfb::SEC_TYPE_VENCRYPT {
+            self.authenticate_with_vencrypt()?;
+        } else if sec_type == rfb::SEC_TYPE_TIGHT {
+            self.authenticate_with_tight_security()?;
+        }
 
-        let protocol_version = self.parse_packet::<rfb::ProtocolVersion>()?;
-        self.write_packet(protocol_version)?;
+        if self.version == rfb::Version::V3_8 {
+            let security_result = self.parse_packet::<rfb::SecurityResult>()?;
+            if let rfb::SecurityResult::Failed(reason) = security_result {
+                return Err(MainError::new(format!(
+                        "Failed security handshake: {}", reason.string)));
+            }
+        } else if sec_type != rfb::SEC_TYPE_NONE {
+            let security_result = 
+                self.parse_packet::<rfb::LegacySecurityResult>()?;
+            if let rfb::LegacySecurityResult::Failed(_) = security_result {
+                return Err(MainError::new(String::from(
+                        "Failed security handshake")));
+            }
+        }
+
+        self.write_packet(rfb::ClientInit {
+            shared: self.config.shared
+        })?;
 
+        let server_init = self.parse_packet::<rfb::ServerInit>()?;
+        if sec_type == rfb::SEC_TYPE_TIGHT {
+            self.skip_tight_interaction_capabilities()?;
+        }
+        Ok(server_init)
+    }
+
+    fn receive_security_type(&mut self) -> Result<u8, MainError> {
+        match self.parse_packet::<rfb::LegacySecurityType>()? {
+            rfb::LegacySecurityType::ErrorReason(error_message) => 
+                Err(MainError::new(format!(
+                        "Server error after version negotiation:\n{}", 
+                        error_message.string))),
+            rfb::LegacySecurityType::Chosen(chosen) => {
+                if chosen.sec_type == rfb::SEC_TYPE_NONE as u32
+                    || chosen.sec_type == rfb::SEC_TYPE_VNC as u32
+                {
+                    Ok(chosen.sec_type as u8)
+                } else {
+                    Err(MainError::new(format!(
+                            "Server requires unsupported security type {}",
+                            chosen.sec_type)))
+                }
+            }
+        }
+    }
+
+    fn choose_security_type(&mut self) -> Result<u8, MainError> {
         let security_types = 
             match self.parse_packet::<rfb::SecurityTypes>()? {
                 rfb::SecurityTypes::ErrorReason(error_message) => 
-                    return Err(MainError(format!(
+                    return Err(MainError::new(format!(
                             "Server error after version negotiation:\n{}", 
                             error_message.string))),
                 rfb::SecurityTypes::SecurityTypesArray(x) => x
             };
-        if !security_types.types.iter().any(|&x| x == rfb::SEC_TYPE_NONE) {
-            return Err(MainError(String::from(
+        let offers = |sec_type| security_types.types.iter()
+            .any(|&x| x == sec_type);
+        let sec_type = if offers(rfb::SEC_TYPE_VENCRYPT) {
+            rfb::SEC_TYPE_VENCRYPT
+        } else if offers(rfb::SEC_TYPE_NONE) {
+            rfb::SEC_TYPE_NONE
+        } else if offers(rfb::SEC_TYPE_VNC) {
+            rfb::SEC_TYPE_VNC
+        } else if offers(rfb::SEC_TYPE_TIGHT) {
+            rfb::SEC_TYPE_TIGHT
+        } else {
+            return Err(MainError::new(String::from(
                     "Server requires authentication. Not implemented yet.")))
-        }
+        };
         
         //eprintln!("received[{}] ‘{:?}’", server_address, security_types.types);
         self.write_packet(rfb::SecurityResponse {
-            sec_type: rfb::SEC_TYPE_NONE
+            sec_type: sec_type
         })?;
+        Ok(sec_type)
+    }
 
-        let security_result = self.parse_packet::<rfb::SecurityResult>()?;
-        if let rfb::SecurityResult::Failed(reason) = security_result {
-            return Err(MainError(
-                    format!("Failed security handshake: {}", reason.string)));
+    fn authenticate_with_vnc_auth(&mut self) -> Result<(), MainError> {
+        let password = match self.config.password {
+            Some(ref password) => password.clone(),
+            None => return Err(MainError::new(String::from(
+                "Server requires VNC authentication but no password was given")))
+        };
+        let challenge = self.parse_packet::<rfb::VncAuthChallenge>()?;
+        self.write_packet(rfb::VncAuthResponse {
+            response: encrypt_vnc_auth_challenge(&password,
+                                                 &challenge.challenge)
+        })
+    }
+
+    fn authenticate_with_tight_security(&mut self) -> Result<(), MainError> {
+        let tunnels = self.parse_packet::<rfb::TightCapabilities>()?;
+        if !tunnels.capabilities.is_empty() {
+            if !tunnels.capabilities.iter()
+                .any(|cap| cap.code == rfb::TIGHT_NO_TUNNEL)
+            {
+                return Err(MainError::new(String::from(
+                    "Server requires a Tight tunnel but none is supported")));
+            }
+            self.write_packet(rfb::TightCapabilityChoice {
+                code: rfb::TIGHT_NO_TUNNEL
+            })?;
         }
 
-        self.write_packet(rfb::ClientInit {
-            shared: true
+        let auth_types = self.parse_packet::<rfb::TightCapabilities>()?
+            .capabilities;
+        if auth_types.is_empty() {
+            return Ok(());
+        }
+        let offers = |code| auth_types.iter().any(|cap| cap.code == code);
+        let auth_type = if offers(rfb::TIGHT_AUTH_NONE) {
+            rfb::TIGHT_AUTH_NONE
+        } else if offers(rfb::TIGHT_AUTH_VNC) {
+            rfb::TIGHT_AUTH_VNC
+        } else {
+            return Err(MainError::new(String::from(
+                "Server requires an unsupported kind of Tight authentication")))
+        };
+        self.write_packet(rfb::TightCapabilityChoice {
+            code: auth_type
         })?;
+        if auth_type == rfb::TIGHT_AUTH_VNC {
+            self.authenticate_with_vnc_auth()?;
+        }
+        Ok(())
+    }
 
-        self.parse_packet::<rfb::ServerInit>()
+    fn skip_tight_interaction_capabilities(&mut self) -> Result<(), MainError> {
+        let counts = self.parse_packet::<rfb::TightInteractionCapabilities>()?;
+        let no_of_capabilities = counts.no_of_server_messages
+            + counts.no_of_client_messages + counts.no_of_encodings;
+        for _ in 0..no_of_capabilities {
+            self.parse_packet::<rfb::TightCapability>()?;
+        }
+        Ok(())
+    }
+
+    fn authenticate_with_vencrypt(&mut self) -> Result<(), MainError> {
+        let version = self.parse_packet::<rfb::VeNCryptVersion>()?;
+        if (version.major, version.minor) < (0, 2) {
+            return Err(MainError::new(format!(
+                        "Unsupported VeNCrypt version {}.{}",
+                        version.major, version.minor)));
+        }
+        self.write_packet(rfb::VeNCryptVersion {
+            major: 0,
+            minor: 2
+        })?;
+        if self.parse_packet::<rfb::VeNCryptStatus>()?.status != 0 {
+            return Err(MainError::new(String::from(
+                "Server does not support VeNCrypt version 0.2")));
+        }
+
+        let subtypes = self.parse_packet::<rfb::VeNCryptSubtypes>()?.subtypes;
+        let subtype = self.choose_vencrypt_subtype(&subtypes)?;
+        self.write_packet(rfb::VeNCryptSubtypeResponse {
+            subtype: subtype
+        })?;
+        if self.parse_packet::<rfb::VeNCryptStatus>()?.status != 1 {
+            return Err(MainError::new(String::from(
+                "Server refused to start a TLS session")));
+        }
+
+        let plain_socket = self.socket.get_ref().try_clone()?;
+        let tls_socket = match subtype {
+            rfb::VENCRYPT_X509_NONE 
+                | rfb::VENCRYPT_X509_VNC 
+                | rfb::VENCRYPT_X509_PLAIN => transport::connect_x509_tls(
+                    plain_socket,
+                    self.config.tls_server_name().ok_or_else(||
+                        MainError::new(String::from(
+                            "No server name to check the certificate \
+                             against (use --tls-server-name=NAME)")))?,
+                    self.config.x509_ca_file.as_ref().map(|p| p.as_path()))?,
+            _ => transport::connect_anonymous_tls(plain_socket)?
+        };
+        self.socket = BufReader::new(Box::new(tls_socket));
+
+        match subtype {
+            rfb::VENCRYPT_TLS_VNC | rfb::VENCRYPT_X509_VNC => 
+                self.authenticate_with_vnc_auth(),
+            rfb::VENCRYPT_TLS_PLAIN | rfb::VENCRYPT_X509_PLAIN => {
+                let credentials = (self.config.username.clone().unwrap(),
+                                   self.config.password.clone().unwrap());
+                self.write_packet(rfb::PlainAuth {
+                    username_and_password: credentials
+                })
+            },
+            _ => Ok(())
+        }
+    }
+
+    fn choose_vencrypt_subtype(&self, offered : &[u32])
+        -> Result<u32, MainError>
+    {
+        let has_password = self.config.password.is_some();
+        let has_username = self.config.username.is_some();
+        let usable = |subtype| match subtype {
+            rfb::VENCRYPT_X509_NONE | rfb::VENCRYPT_TLS_NONE => true,
+            rfb::VENCRYPT_X509_VNC | rfb::VENCRYPT_TLS_VNC => has_password,
+            rfb::VENCRYPT_X509_PLAIN | rfb::VENCRYPT_TLS_PLAIN => 
+                has_password && has_username,
+            _ => false
+        };
+        let preferred_subtypes = [
+            rfb::VENCRYPT_X509_NONE, rfb::VENCRYPT_X509_VNC,
+            rfb::VENCRYPT_X509_PLAIN, rfb::VENCRYPT_TLS_NONE,
+            rfb::VENCRYPT_TLS_VNC, rfb::VENCRYPT_TLS_PLAIN];
+        preferred_subtypes.iter()
+            .cloned()
+            .find(|&subtype| offered.contains(&subtype) && usable(subtype))
+            .ok_or_else(|| MainError::new(format!(
+                "No usable VeNCrypt subtype among {:?} \
+                (a username or password might be missing)", offered)))
+    }
+
+    //the relative mouse server listens next to a server on a display port;
+    //there is no way to find it for reverse, proxied or local connections
+    fn connect_to_mouse_server(&self) -> Result<Option<UdpSocket>, MainError> {
+        if self.config.listen.is_some() || self.config.proxy_command.is_some()
+            || self.config.unix_socket.is_some() || self.config.port < 5900
+        {
+            return Ok(None);
+        }
+        let mouse_server = UdpSocket::bind("0.0.0.0:0")?;
+        mouse_server.connect(
+            (&self.config.host[..], self.config.port - 5900 + 5100))?;
+        Ok(Some(mouse_server))
     }
 
     fn handle_main_part(&mut self, server_init : rfb::ServerInit)
@@ -838,31 +1453,26 @@ impl<V : View> RfbConnection<V> {
         let cursor = Arc::new(Mutex::new(Cursor::new()));
         let decoder = Arc::new(Mutex::new(DecodingMaster::new(
             self.framebuffer.clone(),
-            cursor.clone())));
+            cursor.clone(),
+            self.pixel_converter.clone())));
 
         let (write_end_sender, write_end_receiver) = mpsc::channel();
         let write_end_sender_clone = write_end_sender.clone();
         self.write_end_sender = Some(write_end_sender);
         self.resize_fb(FbSize::new(server_init.width, server_init.height));
 
-        let mouse_server = UdpSocket::bind("0.0.0.0:0")?;
-        mouse_server.connect(
-            (&self.config.host[..], self.config.port - 5900 + 5100))?;
+        let mouse_server = self.connect_to_mouse_server()?;
         let write_end_socket = self.socket.get_ref().try_clone().unwrap();
+        let tight_png = self.config.tight_png;
         let write_end = std::thread::spawn(move || {
             RfbWriteEnd {
                 socket: BufWriter::new(write_end_socket),
                 input: write_end_receiver,
-                mouse_server: mouse_server
+                mouse_server: mouse_server,
+                tight_png: tight_png
             }.handle()
         });
-        let gui_events = self.view.get_events();
-        std::thread::spawn(move || {
-            while let Ok(event) = gui_events.recv() {
-                write_end_sender_clone.send(RfbWriteEvent::GuiEvent(event))
-                    .unwrap_or(());
-            }
-        });
+        *self.write_end_slot.lock().unwrap() = Some(write_end_sender_clone);
 
         let fb_size = self.fb_size();
         self.send_fb_update_request(false, fb_size);
@@ -1029,13 +1639,54 @@ impl<V : View> RfbConnection<V> {
                         }).unwrap_or(());
                     }
                 },
+                rfb::ServerToClient::SetColourMapEntries(entries) => {
+                    self.pixel_converter.set_colours(entries.first_colour,
+                                                     &entries.colours);
+                    decoder.lock().unwrap().set_pixel_converter(
+                        self.pixel_converter.clone());
+                },
                 rfb::ServerToClient::EndOfContinuousUpdates(_) => { },
-                rfb::ServerToClient::ServerCutText(_) => { },
-                rfb::ServerToClient::Bell(_) => { }
+                rfb::ServerToClient::ServerCutText(cut_text) => {
+                    self.handle_cut_text(cut_text.text)?;
+                },
+                rfb::ServerToClient::Bell(_) => {
+                    if self.config.bell != BellMode::Off {
+                        self.view.ring_bell();
+                    }
+                }
             }
         }
     }
 
+    fn handle_cut_text(&mut self, text : rfb::CutText)
+        -> Result<(), MainError>
+    {
+        let text = match text {
+            rfb::CutText::Latin1(text) => text,
+            rfb::CutText::Extended(
+                rfb::ExtendedClipboard::Provide { formats, data }) => {
+                //the connection is fine, only the clipboard is lost
+                match clipboard::decode_provided(formats, &data) {
+                    Ok(Some(text)) => text,
+                    Ok(None) => return Ok(()),
+                    Err(MainError(error_message, _)) => {
+                        eprintln!("{}", error_message);
+                        return Ok(());
+                    }
+                }
+            },
+            rfb::CutText::Extended(message) => {
+                self.write_end().send(RfbWriteEvent::ExtendedClipboard(
+                        message)).unwrap_or(());
+                return Ok(());
+            }
+        };
+        self.write_end().send(RfbWriteEvent::ServerClipboardText(
+                text.clone())).unwrap_or(());
+        self.view.set_clipboard_text(text);
+        Ok(())
+    }
+
     fn read_rectangles_and_start_decoding(
         &mut self, update : rfb::FramebufferUpdate, 
         decoder : &DecodingMaster, maybe_new_fb_size : &mut Option<FbSize>,
@@ -1072,8 +1723,7 @@ impl<V : View> RfbConnection<V> {
         match rectangle.payload {
             //TODO refactor to e.g. rfb::rect::Raw with a pub type
             rfb::RectanglePayload::RawRectangle(_) => {
-                let size = ((PIXEL_FORMAT
-                            .bits_per_pixel as usize) / 8)
+                let size = self.pixel_converter.bytes_per_pixel()
                     * rectangle.width
                     * rectangle.height;
             
@@ -1085,6 +1735,34 @@ impl<V : View> RfbConnection<V> {
                         &rectangle,
                         EncodingMethod::RawBgra(bytes)));
             },
+            rfb::RectanglePayload::CopyRectRectangle(ref payload) => {
+                decoder.accept(DecodingJob::rect_from_rfb(
+                        &rectangle,
+                        EncodingMethod::CopyRect {
+                            src_x: payload.src_x,
+                            src_y: payload.src_y
+                        }));
+            },
+            rfb::RectanglePayload::RreRectangle(ref payload) => {
+                let method = self.read_rre_subrects(
+                    &rectangle, payload.no_of_subrects, |this, pixel_len| {
+                        let subrect : rfb::RreSubrect =
+                            this.parse_parameterized_packet((pixel_len,))?;
+                        Ok((subrect.x, subrect.y, subrect.width,
+                            subrect.height, subrect.color))
+                    })?;
+                decoder.accept(DecodingJob::rect_from_rfb(&rectangle, method));
+            },
+            rfb::RectanglePayload::CorreRectangle(ref payload) => {
+                let method = self.read_rre_subrects(
+                    &rectangle, payload.no_of_subrects, |this, pixel_len| {
+                        let subrect : rfb::CorreSubrect =
+                            this.parse_parameterized_packet((pixel_len,))?;
+                        Ok((subrect.x, subrect.y, subrect.width,
+                            subrect.height, subrect.color))
+                    })?;
+                decoder.accept(DecodingJob::rect_from_rfb(&rectangle, method));
+            },
             rfb::RectanglePayload::TightRectangle(ref payload) => {
                 let zlib_reset_map = payload.control_byte & 0x0f;
                 if zlib_reset_map & 0x01 != 0 {
@@ -1104,19 +1782,17 @@ impl<V : View> RfbConnection<V> {
 
                 match payload.method {
                     rfb::TightMethod::Fill(_) => {
-                        let color = self.parse_packet::<rfb::TPixel>()?;
+                        let color = self.read_tpixel()?;
                         decoder.accept(DecodingJob::rect_from_rfb(
                                 &rectangle,
-                                EncodingMethod::Fill(
-                                    Bgrx::from_tpixel(color))
-                                ));
+                                EncodingMethod::Fill(color)));
                     },
                     rfb::TightMethod::Basic(ref basic) => {
                         match basic.filter {
                             rfb::TightFilter::PaletteFilter(ref palette) => {
                                 let mut colors = Vec::with_capacity(palette.no_of_colors);
                                 for _ in 0..palette.no_of_colors {
-                                    colors.push(Bgrx::from_tpixel(self.parse_packet::<rfb::TPixel>()?));
+                                    colors.push(self.read_tpixel()?);
                                 }
 
                                 let stride = if palette.no_of_colors == 2 {
@@ -1134,18 +1810,29 @@ impl<V : View> RfbConnection<V> {
                                         EncodingMethod::PaletteFilter(colors, data)));
                                 //TODO error out if no_of_colors is 1 (in syntax?)
                             },
-                            _ => {
-                                unimplemented!()
+                            rfb::TightFilter::GradientFilter(_) => {
+                                let uncompressed_size = rectangle.width
+                                    * rectangle.height
+                                    * self.pixel_converter.tpixel_len();
+                                let data = self.read_zlib_data(
+                                    zlib_stream_no,
+                                    uncompressed_size)?;
+                                decoder.accept(DecodingJob::rect_from_rfb(
+                                        &rectangle,
+                                        EncodingMethod::GradientFilter(data)));
+                            },
+                            rfb::TightFilter::CopyFilter(_) => {
+                                let data = self.read_copy_filter_data(
+                                    &rectangle, zlib_stream_no)?;
+                                decoder.accept(DecodingJob::rect_from_rfb(
+                                        &rectangle,
+                                        EncodingMethod::CopyFilter(data)));
                             }
                         }
                     },
                     rfb::TightMethod::BasicNoFilterId(_) => {
-                        //TODO do the same as here for explicit copy filter
-                        let uncompressed_size = rectangle.width
-                            * rectangle.height * TPIXEL_SIZE;
-                        let data = self.read_zlib_data(
-                            zlib_stream_no,
-                            uncompressed_size)?;
+                        let data = self.read_copy_filter_data(
+                            &rectangle, zlib_stream_no)?;
                         decoder.accept(DecodingJob::rect_from_rfb(
                                 &rectangle,
                                 EncodingMethod::CopyFilter(data)));
@@ -1158,10 +1845,85 @@ impl<V : View> RfbConnection<V> {
                     }
                 }
             },
+            rfb::RectanglePayload::TightPngRectangle(ref payload) => {
+                let method = match payload.method {
+                    rfb::TightPngMethod::Fill(_) =>
+                        EncodingMethod::Fill(self.read_tpixel()?),
+                    rfb::TightPngMethod::Jpeg(ref jpeg) =>
+                        EncodingMethod::Jpeg(self.read_bytes(jpeg.length)?),
+                    rfb::TightPngMethod::Png(ref png) =>
+                        EncodingMethod::Png(self.read_bytes(png.length)?)
+                };
+                decoder.accept(DecodingJob::rect_from_rfb(&rectangle, method));
+            },
+            rfb::RectanglePayload::TrleRectangle(_) => {
+                let bounds = Bounds::new(
+                    rectangle.x, rectangle.y,
+                    FbSize::new(rectangle.width, rectangle.height));
+                let cpixel_len = self.pixel_converter.cpixel_len();
+                let mut palette = Vec::new();
+                for tile_bounds in bounds.tiles(TRLE_TILE_SIZE) {
+                    let tile = self.parse_parameterized_packet::<rfb::TrleTile>(
+                        (cpixel_len, tile_bounds.width(), tile_bounds.height(),
+                         palette.len() / cpixel_len))?;
+                    decoder.accept(DecodingJob::Rect {
+                        bounds: tile_bounds,
+                        method: EncodingMethod::TrleTile(
+                            resolve_reused_palette(tile, &mut palette))
+                    });
+                }
+            },
+            rfb::RectanglePayload::HextileRectangle(_) => {
+                let bounds = Bounds::new(
+                    rectangle.x, rectangle.y,
+                    FbSize::new(rectangle.width, rectangle.height));
+                let (mut background, mut foreground) = (None, None);
+                for tile_bounds in bounds.tiles(HEXTILE_TILE_SIZE) {
+                    let tile : rfb::HextileTile =
+                        self.parse_parameterized_packet(
+                            (self.pixel_converter.bytes_per_pixel(),
+                             tile_bounds.width(), tile_bounds.height()))?;
+                    decoder.accept(DecodingJob::Rect {
+                        bounds: tile_bounds,
+                        method: EncodingMethod::HextileTile(
+                            resolve_hextile_colors(tile, &mut background,
+                                                   &mut foreground)?)
+                    });
+                }
+            },
+            rfb::RectanglePayload::ZlibRectangle(ref payload) => {
+                let bytes = self.read_bytes(payload.length)?;
+                decoder.accept(DecodingJob::rect_from_rfb(
+                        &rectangle,
+                        EncodingMethod::Zlib(bytes)));
+            },
+            rfb::RectanglePayload::ZlibHexRectangle(_) => {
+                let bounds = Bounds::new(
+                    rectangle.x, rectangle.y,
+                    FbSize::new(rectangle.width, rectangle.height));
+                let mut tiles = Vec::new();
+                for tile_bounds in bounds.tiles(HEXTILE_TILE_SIZE) {
+                    let tile : rfb::ZlibHexTile =
+                        self.parse_parameterized_packet(
+                            (self.pixel_converter.bytes_per_pixel(),
+                             tile_bounds.width(), tile_bounds.height()))?;
+                    tiles.push((tile_bounds, tile));
+                }
+                decoder.accept(DecodingJob::Rect {
+                    bounds: bounds,
+                    method: EncodingMethod::ZlibHex(tiles)
+                });
+            },
+            rfb::RectanglePayload::ZrleRectangle(ref payload) => {
+                let bytes = self.read_bytes(payload.length)?;
+                decoder.accept(DecodingJob::rect_from_rfb(
+                        &rectangle,
+                        EncodingMethod::Zrle(bytes)));
+            },
             rfb::RectanglePayload::CursorRectangle(_) => {
                 //TODO parse rectangle.size
                 let no_of_pixel_bytes = rectangle.width * rectangle.height
-                    * PIXEL_FORMAT_BYTES_PER_PIXEL;
+                    * self.pixel_converter.bytes_per_pixel();
                 let bitmask_stride = (rectangle.width + 7) / 8;
                 decoder.accept(DecodingJob::rect_from_rfb(
                         &rectangle,
@@ -1185,6 +1947,9 @@ impl<V : View> RfbConnection<V> {
                                                        rectangle.height))));
                 }
             },
+            rfb::RectanglePayload::DesktopNameRectangle(payload) => {
+                self.view.set_title(payload.name);
+            },
             rfb::RectanglePayload::LastRectangle(_) => {
                 return Ok((true, None));
             }
@@ -1193,21 +1958,108 @@ impl<V : View> RfbConnection<V> {
     }
 }
 
+const DEFAULT_LISTEN_PORT : u16 = 5500;
+
+fn read_password_file(path : &str) -> String {
+    let mut contents = String::new();
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .unwrap_or_else(|e| exit_with_error(MainError::new(format!(
+                    "Can't read password file {}: {}", path, e))));
+    contents.lines().next().map(String::from).unwrap_or_default()
+}
+
+fn exit_with_error(MainError(error_message, _) : MainError) -> ! {
+    eprintln!("{}", error_message);
+    std::process::exit(1);
+}
+
 pub fn run(args : Vec<String>) {
     //TODO pass &str
-    //TODO handle parse errors
-    let host_and_port : Vec<&str> = args[1].split(":").collect();
     let options : Vec<_> = args.iter()
-        .skip(2)
-        .take_while(|&s| s.starts_with("--"))
+        .skip(1)
+        .filter(|s| s.starts_with("--"))
         .map(|s| s.as_str())
         .collect();
+    let target = args.iter()
+        .skip(1)
+        .find(|s| !s.starts_with("--"));
+
+    let option_value = |name : &str| options.iter()
+        .find(|s| s.starts_with(name))
+        .map(|s| String::from(&s[name.len()..]));
+    let password = option_value("--password-file=")
+        .map(|path| read_password_file(&path));
+    let listen = if options.contains(&"--listen") {
+        Some(DEFAULT_LISTEN_PORT)
+    } else {
+        option_value("--listen=").map(|port| u16::from_str(&port)
+            .unwrap_or_else(|_| exit_with_error(MainError::new(format!(
+                "Invalid port to listen on: {}", port)))))
+    };
+    let proxy_command = option_value("--proxy-command=");
+    let bell = match option_value("--bell=") {
+        None => BellMode::Audible,
+        Some(bell) => match bell.as_str() {
+            "audible" => BellMode::Audible,
+            "visual" => BellMode::Visual,
+            "off" => BellMode::Off,
+            _ => exit_with_error(MainError::new(format!(
+                "Invalid bell: {} (must be audible, visual or off)", bell)))
+        }
+    };
+    let depth = if options.contains(&"--colour-map") {
+        PixelDepth::ColourMap
+    } else {
+        match option_value("--depth=") {
+            None => PixelDepth::TrueColour24,
+            Some(depth) => match depth.as_str() {
+                "24" => PixelDepth::TrueColour24,
+                "16" => PixelDepth::TrueColour16,
+                "8" => PixelDepth::TrueColour8,
+                _ => exit_with_error(MainError::new(format!(
+                    "Invalid depth: {} (must be 24, 16 or 8)", depth)))
+            }
+        }
+    };
+    //the last of --shared and --exclusive wins
+    let shared = options.iter().rev()
+        .find(|&&s| s == "--shared" || s == "--exclusive")
+        .map_or(true, |&s| s == "--shared");
+
+    let target = match target {
+        Some(target) => parse_target(target)
+            .unwrap_or_else(|err| exit_with_error(err)),
+        None if listen.is_some() || proxy_command.is_some() =>
+            Target::Tcp { host: String::new(), port: 0 },
+        None => exit_with_error(MainError::new(format!(
+            "Usage: {} HOST[:DISPLAY|::PORT]|unix:PATH|vnc://HOST[:PORT] \
+             [OPTIONS] | --listen[=PORT] [OPTIONS] \
+             | --proxy-command=COMMAND [OPTIONS]", args[0])))
+    };
+    let (host, port, unix_socket) = match target {
+        Target::Tcp { host, port } => (host, port, None),
+        Target::Unix(path) => (String::from("localhost"), 0, Some(path))
+    };
 
     let config = ConnectionConfig {
-        host: String::from(host_and_port[0]),
-        port: u16::from_str(host_and_port[1]).unwrap(),
+        host: host,
+        port: port,
         benchmark: options.contains(&"--benchmark"),
-        throttle: options.contains(&"--throttle")
+        throttle: options.contains(&"--throttle"),
+        username: option_value("--username="),
+        password: password,
+        x509_ca_file: option_value("--x509-ca=").map(PathBuf::from),
+        tls_server_name: option_value("--tls-server-name="),
+        listen: listen,
+        proxy_command: proxy_command,
+        unix_socket: unix_socket,
+        reconnect: options.contains(&"--reconnect"),
+        view_only: options.contains(&"--view-only"),
+        shared: shared,
+        depth: depth,
+        bell: bell,
+        tight_png: options.contains(&"--tight-png")
     };
 
     if options.contains(&"--sdl") {
@@ -1216,3 +2068,40 @@ pub fn run(args : Vec<String>) {
         gtk_frontend::run(config);
     }
 }
+
+#[cfg(test)]
+mod the_main_error {
+    use super::*;
+
+    fn kind_of_parse_error(err : ParseError) -> ErrorKind {
+        parse_error((err, 0), "Packet").1
+    }
+
+    #[test]
+    fn should_be_a_transport_failure_if_the_connection_breaks() {
+        let eof = io::Error::new(io::ErrorKind::UnexpectedEof, "eof");
+        assert_eq!(kind_of_parse_error(ParseError::IoError(eof)),
+                   ErrorKind::Transport);
+        let reset = io::Error::new(io::ErrorKind::ConnectionReset, "reset");
+        assert_eq!(kind_of_parse_error(ParseError::IoError(reset)),
+                   ErrorKind::Transport);
+    }
+
+    #[test]
+    fn should_be_a_transport_failure_if_a_packet_cannot_be_written() {
+        let mut output = [0u8; 0];
+        let err = write_packet(rfb::VeNCryptVersion {
+            major: 0,
+            minor: 2
+        }, &mut output[..]).unwrap_err();
+        assert_eq!(err.1, ErrorKind::Transport);
+    }
+
+    #[test]
+    fn should_not_be_a_transport_failure_if_the_server_breaks_the_protocol() {
+        assert_eq!(kind_of_parse_error(ParseError::PredicateFailed("test")),
+                   ErrorKind::Other);
+        assert_eq!(kind_of_parse_error(ParseError::InvalidDiscriminator(7)),
+                   ErrorKind::Other);
+    }
+}
@@ -15,7 +15,7 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use ::{GuiEvent,ProtocolEvent,socket_thread_main,View,ConnectionConfig,
-       MainError,FbSize,EncodingQuality,ViewOutput,PixelFormat};
+       MainError,FbSize,EncodingQuality,ViewOutput,PixelFormat,BellMode};
 use presentation::menu::{Menu,MenuActionHandler,DrawingContext};
 
 use gtk;
@@ -38,6 +38,7 @@ use std::sync::{mpsc,Arc,Mutex};
 use std::ops::Deref;
 
 const COLORSPACE_RGB : i32 = 0;
+const BELL_FLASH_MILLISECONDS : u32 = 100;
 
 struct GtkContext {
     connection_in : mpsc::Receiver<ProtocolEvent>,
@@ -47,7 +48,10 @@ struct GtkContext {
     menu : Menu<GtkMenuActionHandler>,
     current_size : Option<FbSize>,
     fb_updated_tx : mpsc::Sender<()>,
-    fb_updated : bool
+    fb_updated : bool,
+    clipboard : gtk::Clipboard,
+    bell : BellMode,
+    flashing : bool
 }
 static mut GTK_CONTEXT : Option<GtkContext> = None;
 fn gtk_context() -> &'static mut GtkContext {
@@ -112,6 +116,23 @@ fn handle_protocol_event(event : ProtocolEvent) {
         },
         ProtocolEvent::SetTitle(title) => {
             context.window.set_title(&format!("{} — flashvnc", title));
+        },
+        ProtocolEvent::SetClipboardText(text) => {
+            context.clipboard.set_text(&text);
+        },
+        ProtocolEvent::Bell => {
+            if context.bell == BellMode::Visual {
+                context.flashing = true;
+                drawing_area.queue_draw();
+                glib::timeout_add(BELL_FLASH_MILLISECONDS, || {
+                    let context = gtk_context();
+                    context.flashing = false;
+                    context.drawing_area.queue_draw();
+                    glib::Continue(false)
+                });
+            } else if let Some(window) = drawing_area.get_window() {
+                window.beep();
+            }
         }
     }
 }
@@ -201,6 +222,9 @@ impl MenuActionHandler for GtkMenuActionHandler {
         warp_cursor_to_center(&gtk_context().drawing_area);
     }
     fn stop_relative_mouse_mode(&mut self) { }
+    fn set_view_only(&mut self, on : bool) {
+        connection_out().send(GuiEvent::SetViewOnly(on)).unwrap_or(());
+    }
 }
 
 struct CairoContext<'a>(&'a cairo::Context);
@@ -402,6 +426,16 @@ fn handle_keyboard_input(_widget : &gtk::DrawingArea, e : &gdk::EventKey)
     gtk::Inhibit(true)
 }
 
+//waiting for the text would run a nested main loop inside the handler
+fn handle_clipboard_change(clipboard : &gtk::Clipboard) {
+    clipboard.request_text(|_, text| {
+        if let Some(text) = text {
+            connection_out().send(GuiEvent::ClipboardText(String::from(text)))
+                .unwrap_or(());
+        }
+    });
+}
+
 fn handle_resize_event(e : &gdk::EventConfigure) -> bool {
     let (width, height) = e.get_size();
     let size = FbSize::new(width as usize, height as usize);
@@ -439,6 +473,12 @@ pub fn run(config : ConnectionConfig) {
         cr.rectangle(0.0, 0.0, width, height);
         cr.fill();
 
+        if context.flashing {
+            cr.set_source_rgba(1.0, 1.0, 1.0, 0.5);
+            cr.rectangle(0.0, 0.0, width, height);
+            cr.fill();
+        }
+
         if context.menu.visible() {
             context.menu.draw(&mut CairoContext(cr), width, height);
         }
@@ -487,6 +527,11 @@ pub fn run(config : ConnectionConfig) {
 
     area.set_can_focus(true);
 
+    let clipboard = gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD);
+    clipboard.connect_owner_change(|ref clipboard, _| {
+        handle_clipboard_change(clipboard)
+    });
+
     let (gui_events_tx, gui_events_rx) = mpsc::channel();
     let (protocol_events_tx, protocol_events_rx) = mpsc::sync_channel(4);
     let (fb_updated_tx, fb_updated_rx) = mpsc::channel();
@@ -501,10 +546,13 @@ pub fn run(config : ConnectionConfig) {
             drawing_area: area.clone(),
             pixbuf: Pixbuf::new_from_vec(
                     one_pixel_fb, COLORSPACE_RGB, false, 8, 1, 1, 3),
-            menu: Menu::new(GtkMenuActionHandler { }),
+            menu: Menu::new(GtkMenuActionHandler { }, config.view_only),
             current_size: None,
             fb_updated_tx: fb_updated_tx,
-            fb_updated: false
+            fb_updated: false,
+            clipboard: clipboard,
+            bell: config.bell,
+            flashing: false
         });
     }
     let view = GtkView {
@@ -517,7 +565,7 @@ pub fn run(config : ConnectionConfig) {
     std::thread::spawn(move || {
         let main_result = socket_thread_main(config, view);
 
-        if let Err(MainError(error_message)) = main_result {
+        if let Err(MainError(error_message, _)) = main_result {
             show_fatal_error(error_message);
         }
         //TODO call main_quit
@@ -22,6 +22,7 @@ const KEY_F3 : u32 = 0xffc0;
 const KEY_F4 : u32 = 0xffc1;
 const KEY_F5 : u32 = 0xffc2;
 const KEY_F6 : u32 = 0xffc3;
+const KEY_F7 : u32 = 0xffc4;
 const KEY_F8 : u32 = 0xffc5;
 const KEY_F11 : u32 = 0xffc8;
 
@@ -29,14 +30,16 @@ pub struct Menu<H : MenuActionHandler> {
     handler : H,
     f8_pressed : bool,
     relative_mouse_mode : bool,
+    view_only : bool,
     fullscreen : bool
 }
 impl<H : MenuActionHandler> Menu<H> {
-    pub fn new(handler : H) -> Self {
+    pub fn new(handler : H, view_only : bool) -> Self {
         Self {
             handler: handler,
             f8_pressed: false,
             relative_mouse_mode: false,
+            view_only: view_only,
             fullscreen: false
         }
     }
@@ -73,6 +76,10 @@ impl<H : MenuActionHandler> Menu<H> {
                         self.handler.stop_relative_mouse_mode();
                     }
                 },
+                KEY_F7 => {
+                    self.view_only = !self.view_only;
+                    self.handler.set_view_only(self.view_only);
+                },
                 KEY_F11 => {
                     self.fullscreen = !self.fullscreen;
                     if self.fullscreen {
@@ -115,6 +122,7 @@ impl<H : MenuActionHandler> Menu<H> {
             ("F4: Encoding: Lossy, low quality", None),
             ("F5: Encoding: Lossless", None),
             ("F6: Relative mouse mode", Some(self.relative_mouse_mode)),
+            ("F7: View only", Some(self.view_only)),
             ("F11: Fullscreen", Some(self.fullscreen))
         ].iter().enumerate() {
             let y = (i as f64) * item_spacing;
@@ -143,6 +151,7 @@ pub trait MenuActionHandler {
     fn unset_fullscreen(&mut self);
     fn start_relative_mouse_mode(&mut self);
     fn stop_relative_mouse_mode(&mut self);
+    fn set_view_only(&mut self, on : bool);
 }
 
 pub trait DrawingContext {
@@ -27,6 +27,7 @@ use sdl2::pixels::{Color,PixelFormatEnum};
 use sdl2::surface::{Surface,SurfaceRef};
 use sdl2::render::BlendMode;
 use sdl2::video::{Window,FullscreenType};
+use sdl2::clipboard::ClipboardUtil;
 use sdl2::mouse::{MouseUtil,MouseState,Cursor,MouseWheelDirection,MouseButton};
 use sdl2::keyboard::{self,Keycode};
 use sdl2::keyboard::Mod as KeyMod;
@@ -38,11 +39,16 @@ use std::sync::{mpsc,Arc,Mutex};
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::ops::Range;
+use std::time::{Duration,Instant};
 
 use presentation::x11_keysyms;
 
 const ASCII_TEXT_RANGE : Range<u32> = 0x20..0x100;
 const ASCII_DEL : u32 = 127;
+const BELL_FLASH_MILLISECONDS : u64 = 100;
+
+//when the window goes back to the frame it showed before the bell
+type Flash = Option<(Instant, Surface<'static>)>;
 struct KeysymTextRange;
 impl KeysymTextRange {
     pub fn contains(&self, keysym : u32) -> bool {
@@ -106,13 +112,17 @@ fn handle_protocol_event(
     menu : &Menu,
     cursor : &mut Option<Cursor>,
     fb_updated_tx : &mpsc::Sender<()>,
-    mouse : &MouseUtil) 
+    mouse : &MouseUtil,
+    clipboard : &ClipboardUtil,
+    flash : &mut Flash)
 {
     match event {
         ProtocolEvent::ChangeDisplaySize(size) => {
             window.set_size(size.width as u32, size.height as u32).unwrap();
         },
         ProtocolEvent::UpdateFramebuffer(mut bgrx, size) => {
+            //the new frame replaces the flash
+            *flash = None;
             let mut surface = window.surface(events).unwrap();
             let mut image = Surface::from_data(
                 &mut bgrx[..],
@@ -158,6 +168,28 @@ fn handle_protocol_event(
             //window.set_title(&format!("{} — flashvnc", title)).unwrap();
             window.set_title(&format!("{} --- flashvnc", title)).unwrap();
         },
+        ProtocolEvent::SetClipboardText(text) => {
+            clipboard.set_clipboard_text(&text).unwrap_or(());
+        },
+        //SDL cannot beep, so the bell is always visual; further bells
+        //during a flash only make it last longer
+        ProtocolEvent::Bell => {
+            let deadline = Instant::now()
+                + Duration::from_millis(BELL_FLASH_MILLISECONDS);
+            let frame = match flash.take() {
+                Some((_, frame)) => frame,
+                None => {
+                    let mut surface = window.surface(events).unwrap();
+                    let frame = surface.convert(&surface.pixel_format())
+                        .unwrap();
+                    surface.fill_rect(None, Color::RGB(255, 255, 255))
+                        .unwrap();
+                    surface.finish().unwrap();
+                    frame
+                }
+            };
+            *flash = Some((deadline, frame));
+        }
     }
 }
 
@@ -184,6 +216,9 @@ impl MenuActionHandler for SdlMenuActionHandler {
     fn stop_relative_mouse_mode(&mut self) {
         self.mouse.set_relative_mouse_mode(false);
     }
+    fn set_view_only(&mut self, on : bool) {
+        self.gui_events_tx.send(GuiEvent::SetViewOnly(on)).unwrap_or(());
+    }
 }
 
 struct SdlSurface<'a>(&'a mut SurfaceRef);
@@ -378,11 +413,31 @@ struct MainLoop {
     gui_events_tx: mpsc::Sender<GuiEvent>,
     fb_updated_tx : mpsc::Sender<()>,
     cursor : Option<Cursor>,
-    mouse : MouseUtil
+    mouse : MouseUtil,
+    clipboard : ClipboardUtil,
+    flash : Flash
 }
 impl MainLoop {
     pub fn iterate(&mut self) -> bool {
-        let event = self.events.wait_event();
+        let event = match self.flash {
+            Some((deadline, _)) => {
+                let now = Instant::now();
+                let timeout = if deadline > now {
+                    deadline - now
+                } else {
+                    Duration::from_millis(0)
+                };
+                self.events.wait_event_timeout(
+                    timeout.as_secs() as u32 * 1000
+                    + timeout.subsec_nanos() / 1_000_000)
+            },
+            None => Some(self.events.wait_event())
+        };
+        self.end_flash_if_due();
+        let event = match event {
+            Some(event) => event,
+            None => return true
+        };
 
         match event {
             Event::Quit {..} => {
@@ -485,6 +540,13 @@ impl MainLoop {
             Event::Window { win_event: WindowEvent::Resized(w, h), .. } => {
                 let size = FbSize::new(w as usize, h as usize);
                 self.gui_events_tx.send(GuiEvent::Resized(size)).unwrap_or(());
+            },
+
+            Event::ClipboardUpdate { .. } => {
+                if let Ok(text) = self.clipboard.clipboard_text() {
+                    self.gui_events_tx.send(GuiEvent::ClipboardText(text))
+                        .unwrap_or(());
+                }
             }
 
             _ => { }
@@ -496,12 +558,28 @@ impl MainLoop {
                                   &self.menu,
                                   &mut self.cursor,
                                   &self.fb_updated_tx,
-                                  &self.mouse);
+                                  &self.mouse,
+                                  &self.clipboard,
+                                  &mut self.flash);
         }
 
         true
     }
 
+    fn end_flash_if_due(&mut self) {
+        let due = match self.flash {
+            Some((deadline, _)) => Instant::now() >= deadline,
+            None => false
+        };
+        if due {
+            let (_, frame) = self.flash.take().unwrap();
+            let window = self.window.borrow();
+            let mut surface = window.surface(&self.events).unwrap();
+            frame.blit(None, &mut surface, None).unwrap();
+            surface.finish().unwrap();
+        }
+    }
+
     fn handle_key_event(&mut self, keysym : u32, down : bool) {
         //eprintln!("got key: {} {}", keysym, press);
 
@@ -566,6 +644,7 @@ pub fn run(config : ConnectionConfig) {
     let (gui_events_tx, gui_events_rx) = mpsc::channel();
     let (protocol_events_tx, protocol_events_rx) = mpsc::sync_channel(5);
     let (fb_updated_tx, fb_updated_rx) = mpsc::channel();
+    let view_only = config.view_only;
     let view = SdlView {
         events_in: Some(gui_events_rx),
         output: SdlViewOutput {
@@ -577,7 +656,7 @@ pub fn run(config : ConnectionConfig) {
     std::thread::spawn(move || {
         let main_result = socket_thread_main(config, view);
 
-        if let Err(MainError(error_message)) = main_result {
+        if let Err(MainError(error_message, _)) = main_result {
             show_fatal_error(error_message);
             std::process::exit(1);
         }
@@ -588,7 +667,7 @@ pub fn run(config : ConnectionConfig) {
         window: window.clone(),
         gui_events_tx: gui_events_tx.clone(),
         mouse: sdl_context.mouse()
-    });
+    }, view_only);
 
     let mut main_loop = MainLoop {
         window: window,
@@ -599,7 +678,9 @@ pub fn run(config : ConnectionConfig) {
         gui_events_tx: gui_events_tx,
         fb_updated_tx: fb_updated_tx,
         cursor: None,
-        mouse: sdl_context.mouse()
+        mouse: sdl_context.mouse(),
+        clipboard: video.clipboard(),
+        flash: None
     };
 
     while main_loop.iterate() { }
@@ -71,9 +71,13 @@ impl<'a, T> Input<'a> for IoInput<'a, T>
             unsafe {
                 buf.set_len(buf_len + needed);
             }
-            try_parse!(self.clone(), 
-                       self.reader.borrow_mut().read_exact(
-                           &mut buf[self.offset + available..]));
+            let result = self.reader.borrow_mut().read_exact(
+                &mut buf[self.offset + available..]);
+            //other parsers must not see the bytes that were never read
+            if result.is_err() {
+                buf.truncate(buf_len);
+            }
+            try_parse!(self.clone(), result);
         }
         Ok((Ref::map(self.buffer.borrow(), 
                   |buf| &buf[self.offset..(self.offset + len)]), 
@@ -126,4 +130,11 @@ mod the_io_input {
         let message = format!("{:?}", error);
         assert!(message.to_lowercase().contains("eof"));
     }
+
+    #[test]
+    fn should_not_return_unread_bytes_after_an_error() {
+        fixture!(too_short, [1u8]);
+        too_short.read(2).unwrap_err();
+        assert!(too_short.read(1).is_err());
+    }
 }
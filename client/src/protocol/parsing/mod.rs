@@ -97,6 +97,18 @@ pub trait Packet : Sized {
     fn name() -> &'static str;
 }
 
+//a packet whose layout depends on values from earlier packets
+pub trait ParameterizedPacket : Sized {
+    type Params;
+
+    fn parse<I>(buffer : &SharedBuf, input : I, params : Self::Params)
+        -> ParseEndResult<Self>
+        where I : io::Read;
+    fn write<O>(self, output : O, params : Self::Params) -> WriteResult
+        where O : io::Write;
+    fn name() -> &'static str;
+}
+
 pub fn io_parse<P, I>(parser : &P, buffer : &SharedBuf, input : I) 
     -> ParseEndResult<P::T>
     where P : Parser,
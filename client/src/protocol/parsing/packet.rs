@@ -88,6 +88,38 @@ macro_rules! packet_impl {
     }
 }
 
+macro_rules! parameterized_packet_impl {
+    ( $name:ident($($param:ident : $param_t:ty),+) ) => {
+        impl ::protocol::parsing::ParameterizedPacket for $name {
+            type Params = ($($param_t,)+);
+
+            fn parse<I>(
+                buffer : &::protocol::parsing::io_input::SharedBuf,
+                input : I, ($($param,)+) : Self::Params)
+                -> ::protocol::parsing::result::ParseEndResult<Self>
+                where I : ::std::io::Read
+            {
+                ::protocol::parsing::io_parse(
+                    &Self::parser($($param),+), buffer, input)
+            }
+
+            fn write<O>(self, output : O, ($($param,)+) : Self::Params)
+                -> ::protocol::parsing::result::WriteResult
+                where O : ::std::io::Write
+            {
+                ::protocol::parsing::io_write(
+                    &Self::parser($($param),+), output, self)
+            }
+
+            fn name() -> &'static str {
+                stringify!($name)
+            }
+        }
+    }
+}
+
+//with parameters, e.g. packet! { Foo(len : usize): ... }, the parsers of
+//the fields can use them and parser() takes them as arguments
 #[macro_export]
 macro_rules! packet {
     ( $name:ident: 
@@ -95,13 +127,24 @@ macro_rules! packet {
           [ $field:ident : [$($e:tt)*] -> $t:ty ] 
       )+ ) => 
     {
-        packet! { @filter_ignored_fields $name:
+        packet! { @filter_ignored_fields $name():
             [$($field : $t,)+]
             []
             $([$field : [$($e)*] -> $t])+
         }
     };
-    ( @filtered $name:ident: 
+    ( $name:ident($($param:ident : $param_t:ty),+): 
+      $( 
+          [ $field:ident : [$($e:tt)*] -> $t:ty ] 
+      )+ ) => 
+    {
+        packet! { @filter_ignored_fields $name($($param : $param_t),+):
+            [$($field : $t,)+]
+            []
+            $([$field : [$($e)*] -> $t])+
+        }
+    };
+    ( @filtered $name:ident($($param:ident : $param_t:ty),*): 
       $(
           @[ $stored_field:ident : $stored_t:ty ]
       )*
@@ -116,7 +159,9 @@ macro_rules! packet {
             ),*
         }
         impl $name {
-            pub fn parser() -> impl ::protocol::parsing::Parser<T = Self> {
+            pub fn parser($($param : $param_t),*)
+                -> impl ::protocol::parsing::Parser<T = Self>
+            {
                 ::protocol::parsing::primitive::conv(
                     nested_sequence!($(parser!($($e)*)),*),
                     |tuple| {
@@ -131,53 +176,62 @@ macro_rules! packet {
                 )
             }
         }
+        packet! { @impl $name($($param : $param_t),*) }
+    };
+
+    ( @impl $name:ident() ) => {
         packet_impl! {
             $name
         }
     };
+    ( @impl $name:ident($($param:ident : $param_t:ty),+) ) => {
+        parameterized_packet_impl! {
+            $name($($param : $param_t),+)
+        }
+    };
 
-    ( @filter_ignored_fields $name:ident: 
+    ( @filter_ignored_fields $name:ident($($param:ident : $param_t:ty),*): 
       [ignored : $first_t:ty,]
       [$($result:ident : $result_t:ty,)*]
       $( 
           [ $field:ident : [$($e:tt)*] -> $t:ty ] 
       )+ ) => {
-        packet! { @filtered $name:
+        packet! { @filtered $name($($param : $param_t),*):
             $(@[$result : $result_t])*
             $([$field : [$($e)*] -> $t])+
         }
     };
-    ( @filter_ignored_fields $name:ident: 
+    ( @filter_ignored_fields $name:ident($($param:ident : $param_t:ty),*): 
       [$first:ident : $first_t:ty,]
       [$($result:ident : $result_t:ty,)*]
       $( 
           [ $field:ident : [$($e:tt)*] -> $t:ty ] 
       )+ ) => {
-        packet! { @filtered $name:
+        packet! { @filtered $name($($param : $param_t),*):
             $(@[$result : $result_t])*
             @[$first : $first_t]
             $([$field : [$($e)*] -> $t])+
         }
     };
-    ( @filter_ignored_fields $name:ident: 
+    ( @filter_ignored_fields $name:ident($($param:ident : $param_t:ty),*): 
       [ignored : $first_t:ty, $($input:ident : $input_t:ty,)*]
       [$($result:ident : $result_t:ty,)*]
       $( 
           [ $field:ident : [$($e:tt)*] -> $t:ty ] 
       )+ ) => {
-        packet! { @filter_ignored_fields $name:
+        packet! { @filter_ignored_fields $name($($param : $param_t),*):
             [$($input : $input_t,)*]
             [$($result : $result_t,)*]
             $([$field : [$($e)*] -> $t])+
         }
     };
-    ( @filter_ignored_fields $name:ident: 
+    ( @filter_ignored_fields $name:ident($($param:ident : $param_t:ty),*): 
       [$first:ident : $first_t:ty, $($input:ident : $input_t:ty,)*]
       [$($result:ident : $result_t:ty,)*]
       $( 
           [ $field:ident : [$($e:tt)*] -> $t:ty ] 
       )+ ) => {
-        packet! { @filter_ignored_fields $name:
+        packet! { @filter_ignored_fields $name($($param : $param_t),*):
             [$($input : $input_t,)*]
             [$($result : $result_t,)* $first : $first_t,]
             $([$field : [$($e)*] -> $t])+
@@ -361,7 +415,7 @@ macro_rules! tagged_meta_packet {
 
 #[cfg(test)]
 mod the_packet_macro {
-    use protocol::parsing::primitive::{ignored,u8p};
+    use protocol::parsing::primitive::{ignored,u8p,bytes_with_len};
     use protocol::parsing::parser_test::{parse,write};
 
     #[test]
@@ -402,6 +456,20 @@ mod the_packet_macro {
             x: 5
         }).unwrap(), [0, 0, 5, 0]);
     }
+
+    #[test]
+    fn should_pass_parameters_on_to_the_subparsers() {
+        packet! { Foo(len : usize, skipped : usize):
+            [ignored : [ignored(skipped)] -> ()]
+            [bytes : [bytes_with_len(len * 2)] -> Vec<u8>]
+        }
+        let input = [0, 1, 2, 3, 4, 5];
+        assert_eq!(parse(&Foo::parser(2, 1), &input[..]).unwrap().bytes,
+                   [1, 2, 3, 4]);
+        assert_eq!(write(&Foo::parser(1, 2), Foo {
+            bytes: vec![7, 8]
+        }).unwrap(), [0, 0, 7, 8]);
+    }
 }
 
 #[cfg(test)]
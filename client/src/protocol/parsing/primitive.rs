@@ -259,6 +259,88 @@ pub fn utf8() -> Utf8 {
     }
 }
 
+//characters outside of Latin-1 are written as ?
+pub struct Latin1 {
+    len : Option<usize>
+}
+impl Parser for Latin1 {
+    type T = String;
+    fn parse<'a, I>(&self, input : I) -> ParseResult<String, I>
+        where I : Input<'a>
+    {
+        self.parse_with_params(input, self.len.unwrap())
+    }
+    fn write<O>(&self, output : &mut O, value : String) -> WriteResult
+        where O : Output
+    {
+        let bytes : Vec<u8> = value.chars()
+            .map(|c| if (c as u32) < 0x100 { c as u8 } else { b'?' })
+            .collect();
+        output.write(&bytes[..])
+    }
+}
+impl ParameterizedParser for Latin1 {
+    type Params = usize;
+
+    fn parse_with_params<'a, I>(&self, mut input : I, len : usize)
+        -> ParseResult<Self::T, I>
+        where I : Input<'a>
+    {
+        let (bytes, rest) = input.read(len)?;
+        Ok((bytes.iter().map(|&byte| byte as char).collect(), rest))
+    }
+    fn extract_params(&self, value : &String) -> usize {
+        value.chars().count()
+    }
+}
+pub fn latin1() -> Latin1 {
+    Latin1 {
+        len: None
+    }
+}
+
+pub struct Bytes {
+    len : Option<usize>
+}
+impl Parser for Bytes {
+    type T = Vec<u8>;
+    fn parse<'a, I>(&self, input : I) -> ParseResult<Vec<u8>, I>
+        where I : Input<'a>
+    {
+        self.parse_with_params(input, self.len.unwrap())
+    }
+    fn write<O>(&self, output : &mut O, value : Vec<u8>) -> WriteResult
+        where O : Output
+    {
+        //TODO check correct length
+        output.write(&value[..])
+    }
+}
+impl ParameterizedParser for Bytes {
+    type Params = usize;
+
+    fn parse_with_params<'a, I>(&self, mut input : I, len : usize)
+        -> ParseResult<Self::T, I>
+        where I : Input<'a>
+    {
+        let (bytes, rest) = input.read(len)?;
+        Ok((bytes.iter().cloned().collect(), rest))
+    }
+    fn extract_params(&self, value : &Vec<u8>) -> usize {
+        value.len()
+    }
+}
+pub fn bytes_with_len(len : usize) -> Bytes {
+    Bytes {
+        len: Some(len)
+    }
+}
+pub fn bytes() -> Bytes {
+    Bytes {
+        len: None
+    }
+}
+
 pub struct Seq<P1, P2>
     where P1 : Parser,
           P2 : Parser
@@ -394,7 +476,13 @@ impl<P1, P2, T> Parser for Opt<P1, P2, T>
     {
         match self.p1.parse(input.clone()) {
             Ok(x) => Ok(x),
-            Err(_) => self.p2.parse(input)
+            //the end of the input explains why the second one fails, too
+            Err((first_error, _)) => self.p2.parse(input)
+                .map_err(|(error, rest)| if first_error.is_eof() {
+                    (first_error, rest)
+                } else {
+                    (error, rest)
+                })
         }
     }
     fn write<O>(&self, output : &mut O, value : Self::T) -> WriteResult
@@ -555,6 +643,58 @@ pub fn array<P>(p : P) -> Array<P>
     Array { p: p }
 }
 
+//items until their lengths add up to the total, like runs of pixels that
+//fill a rectangle
+pub struct Runs<P, F>
+    where P : Parser,
+          F : Fn(&P::T) -> usize
+{
+    p : P,
+    total : usize,
+    run_length : F
+}
+impl<P, F> Parser for Runs<P, F>
+    where P : Parser,
+          F : Fn(&P::T) -> usize
+{
+    type T = Vec<P::T>;
+    fn parse<'a, I>(&self, mut input : I) -> ParseResult<Self::T, I>
+        where I : Input<'a>
+    {
+        let mut ret = Vec::new();
+        let mut covered = 0;
+        while covered < self.total {
+            let (item, rest) = self.p.parse(input)?;
+            covered += (self.run_length)(&item);
+            ret.push(item);
+            input = rest;
+        }
+        if covered > self.total {
+            return Err((ParseError::PredicateFailed(
+                        "runs should not exceed the total length"), input));
+        }
+        Ok((ret, input))
+    }
+    fn write<O>(&self, output : &mut O, vec : Self::T) -> WriteResult
+        where O : Output
+    {
+        for item in vec {
+            self.p.write(output, item)?;
+        }
+        Ok(())
+    }
+}
+pub fn runs<P, F>(p : P, total : usize, run_length : F) -> Runs<P, F>
+    where P : Parser,
+          F : Fn(&P::T) -> usize
+{
+    Runs {
+        p: p,
+        total: total,
+        run_length: run_length
+    }
+}
+
 //pub struct LazyVec<T> {
 //    len : usize
 //}
@@ -709,6 +849,45 @@ mod the_u8_parser {
     }
 }
 
+#[cfg(test)]
+mod the_bytes_parser {
+    use super::*;
+    use protocol::parsing::parser_test::*;
+
+    #[test]
+    fn should_read_the_specified_number_of_bytes_unchanged() {
+        let parser = seq(bytes_with_len(2), u8p());
+        assert_eq!(parse(&parser, &[0xff, 0, 7][..]).unwrap(),
+                   (vec![0xff, 0], 7));
+    }
+
+    #[test]
+    fn should_write_a_length_prefix_if_used_with_dep() {
+        let parser = dep(length(u8p()), bytes());
+        assert_eq!(write(&parser, vec![5, 6]).unwrap(), [2, 5, 6]);
+    }
+}
+
+#[cfg(test)]
+mod the_latin1_parser {
+    use super::*;
+    use protocol::parsing::parser_test::*;
+
+    #[test]
+    fn should_read_every_byte_as_one_character() {
+        let parser = dep(length(u8p()), latin1());
+        assert_eq!(parse(&parser, &[3, 0x61, 0xe9, 0xff][..]).unwrap(),
+                   "a\u{e9}\u{ff}");
+    }
+
+    #[test]
+    fn should_write_characters_outside_of_latin1_as_question_marks() {
+        let parser = dep(length(u8p()), latin1());
+        assert_eq!(write(&parser, String::from("\u{fc}\u{20ac}a")).unwrap(),
+                   [3, 0xfc, b'?', b'a']);
+    }
+}
+
 #[cfg(test)]
 mod the_ignored_parser {
     use super::*;
@@ -830,6 +1009,24 @@ mod the_pred_parser {
     }
 }
 
+#[cfg(test)]
+mod the_opt_parser {
+    use super::*;
+    use protocol::parsing::parser_test::parse;
+
+    #[test]
+    fn should_try_the_second_parser_if_the_first_one_fails() {
+        let parser = opt(literal(u8p(), 1), literal(u8p(), 2));
+        assert_eq!(parse(&parser, &[2u8][..]).unwrap(), ());
+    }
+
+    #[test]
+    fn should_report_the_end_of_the_input_if_both_fail() {
+        let parser = opt(literal(u16_be(), 1), literal(u8p(), 2));
+        assert!(parse(&parser, &[3u8][..]).unwrap_err().0.is_eof());
+    }
+}
+
 #[cfg(test)]
 mod the_dep_parser {
     use super::*;
@@ -886,6 +1083,32 @@ mod dep_with_array_parser {
     }
 }
 
+#[cfg(test)]
+mod the_runs_parser {
+    use super::*;
+    use protocol::parsing::parser_test::*;
+
+    fn parser(total : usize) -> impl Parser<T = Vec<(u8, u8)>> {
+        runs(seq(u8p(), u8p()), total, |&(_, n)| n as usize)
+    }
+
+    #[test]
+    fn should_parse_items_until_their_lengths_reach_the_total() {
+        assert_eq!(parse(&parser(5), &[7, 2, 8, 3, 9, 1][..]).unwrap(),
+                   [(7, 2), (8, 3)]);
+        assert_eq!(write(&parser(5), vec![(7, 2), (8, 3)]).unwrap(),
+                   [7, 2, 8, 3]);
+    }
+
+    #[test]
+    fn should_fail_if_the_last_item_goes_past_the_total() {
+        match parse(&parser(4), &[7, 2, 8, 3][..]).unwrap_err().0 {
+            ParseError::PredicateFailed(_) => { },
+            _ => assert!(false)
+        }
+    }
+}
+
 #[cfg(test)]
 mod the_literal_parser {
     use super::*;
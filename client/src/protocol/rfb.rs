@@ -14,9 +14,9 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use protocol::parsing::primitive::{u8p,seq,conv,pred,utf8_with_len,dep,length,prefix_len_array,u32_be,utf8,non_zero,literal,u8_bool,u16_be,ignored,i32_be,nothing,zero_len};
+use protocol::parsing::primitive::{u8p,seq,conv,pred,utf8_with_len,dep,length,prefix_len_array,u32_be,utf8,non_zero,literal,u8_bool,u16_be,ignored,i32_be,nothing,zero_len,bytes_with_len,runs,ParameterizedParser,bytes,latin1};
 use protocol::parsing::{Parser,Input,Output};
-use protocol::parsing::result::{ParseResult,WriteResult,WriteError};
+use protocol::parsing::result::{ParseResult,ParseError,WriteResult,WriteError};
 
 pub const PROTOCOL_VERSION_LEN : usize = 12;
 
@@ -26,15 +26,37 @@ pub const PROTOCOL_VERSION_LEN : usize = 12;
 //  [string : [utf8_with_len(12) if |s| == "RFB 003.008\n" else "should be"] -> String]
 
 pub const SEC_TYPE_NONE : u8 = 1;
-//TODO check
 pub const SEC_TYPE_VNC : u8 = 2;
 pub const SEC_TYPE_TIGHT : u8 = 16;
+pub const SEC_TYPE_VENCRYPT : u8 = 19;
+
+pub const TIGHT_NO_TUNNEL : u32 = 0;
+pub const TIGHT_AUTH_NONE : u32 = 1;
+pub const TIGHT_AUTH_VNC : u32 = 2;
+
+pub const VENCRYPT_TLS_NONE : u32 = 257;
+pub const VENCRYPT_TLS_VNC : u32 = 258;
+pub const VENCRYPT_TLS_PLAIN : u32 = 259;
+pub const VENCRYPT_X509_NONE : u32 = 260;
+pub const VENCRYPT_X509_VNC : u32 = 261;
+pub const VENCRYPT_X509_PLAIN : u32 = 262;
+
+pub const VNC_AUTH_CHALLENGE_LEN : usize = 16;
 
 const SEC_RESULT_OK : u32 = 0;
 const SEC_RESULT_FAILED : u32 = 1;
 
 pub const ENCODING_RAW : i32 = 0;
+pub const ENCODING_COPY_RECT : i32 = 1;
+pub const ENCODING_RRE : i32 = 2;
+pub const ENCODING_CORRE : i32 = 4;
+pub const ENCODING_HEXTILE : i32 = 5;
+pub const ENCODING_ZLIB : i32 = 6;
+pub const ENCODING_ZLIB_HEX : i32 = 8;
 pub const ENCODING_TIGHT : i32 = 7;
+pub const ENCODING_TIGHT_PNG : i32 = -260;
+pub const ENCODING_TRLE : i32 = 15;
+pub const ENCODING_ZRLE : i32 = 16;
 
 pub const ENCODING_WORST_JPEG_QUALITY : i32 = -512;
 pub const ENCODING_BEST_JPEG_QUALITY : i32 = -412;
@@ -46,10 +68,12 @@ pub const ENCODING_CHROMA_SUBSAMPLING_4X : i32 = -767;
 pub const ENCODING_DESKTOP_SIZE : i32 = -223;
 pub const ENCODING_CURSOR : i32 = -239;
 pub const ENCODING_EXTENDED_DESKTOP_SIZE : i32 = -308;
+pub const ENCODING_DESKTOP_NAME : i32 = -307;
 
 pub const ENCODING_LAST_RECT : i32 = -224;
 pub const ENCODING_CONTINUOUS_UPDATES : i32 = -313;
 pub const ENCODING_FENCE : i32 = -312;
+pub const ENCODING_EXTENDED_CLIPBOARD : i32 = 0xc0a1e5ce_u32 as i32;
 
 pub const EXTENDED_DESKTOP_NO_ERROR : usize = 0;
 
@@ -62,6 +86,7 @@ fn is_security_type(&number : &u8) -> bool {
     number == SEC_TYPE_NONE
         || number == SEC_TYPE_VNC
         || number == SEC_TYPE_TIGHT
+        || number == SEC_TYPE_VENCRYPT
 }
 
 //test
@@ -110,11 +135,75 @@ pub fn compact_length() -> impl Parser<T = usize> {
     CompactLength { }
 }
 
+//both lengths come before both strings
+struct PlainCredentials;
+impl Parser for PlainCredentials {
+    type T = (String, String);
+    fn parse<'a, I>(&self, input : I) -> ParseResult<(String, String), I>
+        where I : Input<'a>
+    {
+        let lengths = seq(length(u32_be()), length(u32_be()));
+        let ((username_len, password_len), input) = lengths.parse(input)?;
+        let (username, input) = utf8().parse_with_params(input, username_len)?;
+        let (password, input) = utf8().parse_with_params(input, password_len)?;
+        Ok(((username, password), input))
+    }
+    fn write<O>(&self, output : &mut O, (username, password) : (String, String))
+        -> WriteResult
+        where O : Output
+    {
+        let lengths = seq(length(u32_be()), length(u32_be()));
+        lengths.write(output, (username.len(), password.len()))?;
+        utf8().write(output, username)?;
+        utf8().write(output, password)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Version {
+    V3_3,
+    V3_7,
+    V3_8
+}
+
+fn is_version_string(string : &String) -> bool {
+    let bytes = string.as_bytes();
+    let is_digit = |&b : &u8| b >= b'0' && b <= b'9';
+    bytes.len() == PROTOCOL_VERSION_LEN
+        && &bytes[0..4] == b"RFB " && bytes[7] == b'.' && bytes[11] == b'\n'
+        && bytes[4..7].iter().all(&is_digit) && bytes[8..11].iter().all(&is_digit)
+}
+
 packet! { ProtocolVersion:
     [string : [pred(
-        utf8_with_len(12),
-        |s| s == "RFB 003.008\n",
-        "should be RFB version 3.8")] -> String]
+        utf8_with_len(PROTOCOL_VERSION_LEN),
+        is_version_string,
+        "should be an RFB version like RFB 003.008")] -> String]
+}
+impl ProtocolVersion {
+    pub fn new(version : Version) -> Self {
+        Self {
+            string: String::from(match version {
+                Version::V3_3 => "RFB 003.003\n",
+                Version::V3_7 => "RFB 003.007\n",
+                Version::V3_8 => "RFB 003.008\n"
+            })
+        }
+    }
+
+    //the newest version both this client and the server can speak;
+    //unknown minor versions have to be treated as 3.3
+    pub fn negotiate(&self) -> Option<Version> {
+        let major : u32 = self.string[4..7].parse().unwrap();
+        let minor : u32 = self.string[8..11].parse().unwrap();
+        match (major, minor) {
+            (3, minor) if minor >= 8 => Some(Version::V3_8),
+            (3, 7) => Some(Version::V3_7),
+            (3, minor) if minor >= 3 => Some(Version::V3_3),
+            (major, _) if major > 3 => Some(Version::V3_8),
+            _ => None
+        }
+    }
 }
 
 packet! { ErrorReason:
@@ -130,12 +219,64 @@ meta_packet! { SecurityTypes:
     SecurityTypesArray,
     [u8p() => 0] ErrorReason
 }
+//only in version 3.3, in which the server decides
+packet! { ServerSecurityType:
+    [sec_type : [non_zero(u32_be())] -> u32]
+}
+meta_packet! { LegacySecurityType:
+    Chosen(ServerSecurityType),
+    [u32_be() => 0] ErrorReason(ErrorReason)
+}
+
 packet! { SecurityResponse:
     [sec_type : [pred(u8p(), 
                       is_security_type, 
                       "should be security type")] -> u8]
 }
 
+packet! { VncAuthChallenge:
+    [challenge : [bytes_with_len(VNC_AUTH_CHALLENGE_LEN)] -> Vec<u8>]
+}
+packet! { VncAuthResponse:
+    [response : [bytes_with_len(VNC_AUTH_CHALLENGE_LEN)] -> Vec<u8>]
+}
+
+packet! { VeNCryptVersion:
+    [major : [u8p()] -> u8]
+    [minor : [u8p()] -> u8]
+}
+packet! { VeNCryptStatus:
+    [status : [u8p()] -> u8]
+}
+packet! { VeNCryptSubtypes:
+    [subtypes : [prefix_len_array(u8p(), u32_be())] -> Vec<u32>]
+}
+packet! { VeNCryptSubtypeResponse:
+    [subtype : [u32_be()] -> u32]
+}
+packet! { PlainAuth:
+    [username_and_password : [PlainCredentials] -> (String, String)]
+}
+
+packet! { TightCapability:
+    [code : [u32_be()] -> u32]
+    [vendor : [utf8_with_len(4)] -> String]
+    [name : [utf8_with_len(8)] -> String]
+}
+packet! { TightCapabilities:
+    [capabilities : [prefix_len_array(
+        u32_be(), TightCapability::parser())] -> Vec<TightCapability>]
+}
+packet! { TightCapabilityChoice:
+    [code : [u32_be()] -> u32]
+}
+packet! { TightInteractionCapabilities:
+    [no_of_server_messages : [length(u16_be())] -> usize]
+    [no_of_client_messages : [length(u16_be())] -> usize]
+    [no_of_encodings : [length(u16_be())] -> usize]
+    [ignored : [ignored(2)] -> ()]
+}
+
 packet! { SecurityResultOk:
     [ignored : [literal(u32_be(), SEC_RESULT_OK)] -> ()]
 }
@@ -143,6 +284,14 @@ meta_packet! { SecurityResult:
     Ok(SecurityResultOk),
     [u32_be() => SEC_RESULT_FAILED] Failed(ErrorReason)
 }
+//there is no reason before version 3.8
+packet! { SecurityResultFailed:
+    [ignored : [literal(u32_be(), SEC_RESULT_FAILED)] -> ()]
+}
+meta_packet! { LegacySecurityResult:
+    Ok(SecurityResultOk),
+    Failed(SecurityResultFailed)
+}
 
 packet! { ClientInit:
     [shared : [u8_bool()] -> bool]
@@ -204,6 +353,12 @@ packet! { PointerEvent:
     [y : [u16_be()] -> u16]
 }
 
+//like ServerCutText
+packet! { ClientCutText:
+    [ignored : [ignored(3)] -> ()]
+    [text : [CutTextParser] -> CutText]
+}
+
 packet! { EnableContinuousUpdates:
     [enable : [u8_bool()] -> bool]
     [x : [length(u16_be())] -> usize]
@@ -234,6 +389,7 @@ tagged_meta_packet! { ClientToServer: u8p() => u8 =>
     [3] FramebufferUpdateRequest,
     [4] KeyEvent,
     [5] PointerEvent,
+    [6] ClientCutText,
     [150] EnableContinuousUpdates,
     [248] Fence,
     [251] SetDesktopSize
@@ -248,6 +404,32 @@ packet! { TPixel:
 packet! { RawRectangle:
     [ignored : [nothing()] -> ()] //read bytes yourself, force client somehow?
 }
+packet! { CopyRectRectangle:
+    [src_x : [length(u16_be())] -> usize]
+    [src_y : [length(u16_be())] -> usize]
+}
+//the background pixel and the subrectangles follow, which the caller reads
+packet! { RreRectangle:
+    [no_of_subrects : [length(u32_be())] -> usize]
+}
+//CoRRE is RRE with 8 bit coordinates and sizes
+packet! { CorreRectangle:
+    [no_of_subrects : [length(u32_be())] -> usize]
+}
+packet! { RreSubrect(pixel_len : usize):
+    [color : [bytes_with_len(pixel_len)] -> Vec<u8>]
+    [x : [length(u16_be())] -> usize]
+    [y : [length(u16_be())] -> usize]
+    [width : [length(u16_be())] -> usize]
+    [height : [length(u16_be())] -> usize]
+}
+packet! { CorreSubrect(pixel_len : usize):
+    [color : [bytes_with_len(pixel_len)] -> Vec<u8>]
+    [x : [length(u8p())] -> usize]
+    [y : [length(u8p())] -> usize]
+    [width : [length(u8p())] -> usize]
+    [height : [length(u8p())] -> usize]
+}
 packet! { TightFill:
     [control_byte : [pred(u8p(), |n| n & 0xf0 == 0b1000_0000, 
                           "bits 7..4 must be 1000")] -> u8]
@@ -258,7 +440,8 @@ packet! { TightJpeg:
     [length : [compact_length()] -> usize]
 }
 packet! { TightZlib:
-    //TODO note that, if the uncompressed length is less than 12, there is no compression!
+    //not sent if the uncompressed data is shorter than 12 bytes; the data is
+    //not compressed then
     [length : [compact_length()] -> usize]
 }
 packet! { CopyFilter:
@@ -295,6 +478,25 @@ packet! { TightRectangle:
     [control_byte : [zero_len(u8p())] -> u8]
     [method : [TightMethod::parser()] -> TightMethod]
 }
+//TightPNG has no basic compression but PNG instead
+packet! { TightPng:
+    [control_byte : [pred(u8p(), |n| n & 0xf0 == 0b1010_0000, 
+                          "bits 7..4 must be 1010")] -> u8]
+    [length : [compact_length()] -> usize]
+}
+meta_packet! { TightPngMethod:
+    Jpeg(TightJpeg),
+    Fill(TightFill),
+    Png(TightPng)
+}
+packet! { TightPngRectangle:
+    [control_byte : [zero_len(u8p())] -> u8]
+    [method : [TightPngMethod::parser()] -> TightPngMethod]
+}
+packet! { ZrleRectangle:
+    //of the zlib data that follows
+    [length : [length(u32_be())] -> usize]
+}
 packet! { DesktopSizeRectangle:
     [ignored : [nothing()] -> ()]
 }
@@ -315,30 +517,550 @@ packet! { ExtendedDesktopSizeRectangle:
         conv(seq(u8p(), ignored(3)), |(n, ())| n, |n| Ok((n, ()))),
         Screen::parser())] -> Vec<Screen>]
 }
+packet! { DesktopNameRectangle:
+    [name : [dep(length(u32_be()), utf8())] -> String]
+}
 packet! { LastRectangle:
     [ignored : [nothing()] -> ()]
 }
-//TODO solution to everything: step-by-step parsing(lazy parser)
-//packet! { TrleTile(cpixel_len : usize, width : usize, height : usize):
-//    u8p() => {
-//        [0] { bytes : [bytes(width * height * cpixel_len)] -> Vec<u8> }
-//        [1] { color : [cpixel(cpixel_len)] -> Pixel },
-//        [x = 2..16] { palette : [array_with_len(2, cpixel(cpixel_len))] -> Vec<Pixel>, 
-//            pixels : [bytes(ceil(width / match x { 2 => 8, x if x <= 4 => 4, _ => 8 }))] }, //need to check correctness of bit fields yourself
-//        [127] { pixels : like above },
-//        [128] { //repeated until the tile is done???? }
-//    }
-//}
-//packet! { TrleRectangle:
-//    [tiles]
-//}
+//TRLE tiles are read one after the other by the caller, ZRLE contains them
+//in its zlib data
+packet! { TrleRectangle:
+    [ignored : [nothing()] -> ()]
+}
+//like TRLE tiles, Hextile tiles are read one after the other by the caller
+packet! { HextileRectangle:
+    [ignored : [nothing()] -> ()]
+}
+packet! { ZlibRectangle:
+    [length : [length(u32_be())] -> usize]
+}
+packet! { ZlibHexRectangle:
+    [ignored : [nothing()] -> ()]
+}
+
+//sum of all bytes up to and including the first one that is not 255,
+//plus one
+struct RunLength;
+impl Parser for RunLength {
+    type T = usize;
+    fn parse<'a, I>(&self, mut input : I) -> ParseResult<usize, I>
+        where I : Input<'a>
+    {
+        let mut ret = 1;
+        loop {
+            let (byte, rest) = input.read(1)?;
+            input = rest;
+            ret += byte[0] as usize;
+            if byte[0] != 255 {
+                return Ok((ret, input));
+            }
+        }
+    }
+    fn write<O>(&self, output : &mut O, value : usize) -> WriteResult
+        where O : Output
+    {
+        if value == 0 {
+            return Err(WriteError::PredicateFailed("run length must not be 0"));
+        }
+        let mut rest = value - 1;
+        while rest >= 255 {
+            output.write(&[255])?;
+            rest -= 255;
+        }
+        output.write(&[rest as u8])
+    }
+}
+pub fn run_length() -> impl Parser<T = usize> {
+    RunLength { }
+}
+
+//an index into the palette, with a run length if the msb is set
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaletteRun {
+    pub index : u8,
+    pub length : usize
+}
+struct PaletteRunParser;
+impl Parser for PaletteRunParser {
+    type T = PaletteRun;
+    fn parse<'a, I>(&self, input : I) -> ParseResult<PaletteRun, I>
+        where I : Input<'a>
+    {
+        let (byte, input) = u8p().parse(input)?;
+        let (length, input) = if byte & 0x80 != 0 {
+            run_length().parse(input)?
+        } else {
+            (1, input)
+        };
+        Ok((PaletteRun {
+            index: byte & 0x7f,
+            length: length
+        }, input))
+    }
+    fn write<O>(&self, output : &mut O, run : PaletteRun) -> WriteResult
+        where O : Output
+    {
+        if run.index > 0x7f {
+            return Err(WriteError::PredicateFailed("index too large"));
+        }
+        if run.length == 1 {
+            output.write(&[run.index])
+        } else {
+            output.write(&[run.index | 0x80])?;
+            run_length().write(output, run.length)
+        }
+    }
+}
+pub fn palette_run() -> impl Parser<T = PaletteRun> {
+    PaletteRunParser { }
+}
+
+//each row of a tile starts at a new byte
+pub fn packed_palette_bits_per_index(palette_len : usize) -> usize {
+    match palette_len {
+        0..=2 => 1,
+        3 | 4 => 2,
+        _ => 4
+    }
+}
+fn packed_palette_len(palette_len : usize, width : usize, height : usize)
+    -> usize
+{
+    (width * packed_palette_bits_per_index(palette_len) + 7) / 8 * height
+}
+
+//CPIXELs are pixels without the bytes that are always unused; palettes
+//are stored as they are sent
+packet! { TrleRaw(cpixel_len : usize, width : usize, height : usize):
+    [pixels : [bytes_with_len(cpixel_len * width * height)] -> Vec<u8>]
+}
+packet! { TrleSolid(cpixel_len : usize):
+    [color : [bytes_with_len(cpixel_len)] -> Vec<u8>]
+}
+packet! { TrlePackedPalette(palette_len : usize, cpixel_len : usize,
+                            width : usize, height : usize):
+    [palette : [bytes_with_len(palette_len * cpixel_len)] -> Vec<u8>]
+    [indices : [bytes_with_len(
+        packed_palette_len(palette_len, width, height))] -> Vec<u8>]
+}
+packet! { TrleReusedPackedPalette(palette_len : usize,
+                                  width : usize, height : usize):
+    [indices : [bytes_with_len(
+        packed_palette_len(palette_len, width, height))] -> Vec<u8>]
+}
+packet! { TrleRun(cpixel_len : usize):
+    [color : [bytes_with_len(cpixel_len)] -> Vec<u8>]
+    [length : [run_length()] -> usize]
+}
+packet! { TrlePlainRle(cpixel_len : usize, width : usize, height : usize):
+    [runs : [runs(TrleRun::parser(cpixel_len), width * height,
+                  |run : &TrleRun| run.length)] -> Vec<TrleRun>]
+}
+packet! { TrlePaletteRle(palette_len : usize, cpixel_len : usize,
+                         width : usize, height : usize):
+    [palette : [bytes_with_len(palette_len * cpixel_len)] -> Vec<u8>]
+    [runs : [runs(palette_run(), width * height,
+                  |run : &PaletteRun| run.length)] -> Vec<PaletteRun>]
+}
+packet! { TrleReusedPaletteRle(width : usize, height : usize):
+    [runs : [runs(palette_run(), width * height,
+                  |run : &PaletteRun| run.length)] -> Vec<PaletteRun>]
+}
+
+const TRLE_RAW : u8 = 0;
+const TRLE_SOLID : u8 = 1;
+const TRLE_REUSED_PACKED_PALETTE : u8 = 127;
+const TRLE_PLAIN_RLE : u8 = 128;
+const TRLE_REUSED_PALETTE_RLE : u8 = 129;
+
+//the subencoding is the palette size for packed palettes and the palette
+//size plus 128 for palette RLE
+#[derive(Debug, Clone)]
+pub enum TrleTile {
+    Raw(TrleRaw),
+    Solid(TrleSolid),
+    PackedPalette(TrlePackedPalette),
+    //with the palette of the previous tile
+    ReusedPackedPalette(TrleReusedPackedPalette),
+    PlainRle(TrlePlainRle),
+    PaletteRle(TrlePaletteRle),
+    ReusedPaletteRle(TrleReusedPaletteRle)
+}
+impl TrleTile {
+    //ZRLE has no tiles that reuse palettes, so previous_palette_len is 0
+    pub fn parser(cpixel_len : usize, width : usize, height : usize,
+                  previous_palette_len : usize)
+        -> impl Parser<T = Self>
+    {
+        TrleTileParser {
+            cpixel_len: cpixel_len,
+            width: width,
+            height: height,
+            previous_palette_len: previous_palette_len
+        }
+    }
+}
+parameterized_packet_impl! {
+    TrleTile(cpixel_len : usize, width : usize, height : usize,
+             previous_palette_len : usize)
+}
+
+fn parse_variant<'a, P, F, T, I>(parser : P, variant : F, input : I)
+    -> ParseResult<T, I>
+    where P : Parser,
+          F : Fn(P::T) -> T,
+          I : Input<'a>
+{
+    let (value, rest) = parser.parse(input)?;
+    Ok((variant(value), rest))
+}
+
+struct TrleTileParser {
+    cpixel_len : usize,
+    width : usize,
+    height : usize,
+    previous_palette_len : usize
+}
+impl Parser for TrleTileParser {
+    type T = TrleTile;
+    fn parse<'a, I>(&self, input : I) -> ParseResult<TrleTile, I>
+        where I : Input<'a>
+    {
+        use self::TrleTile::*;
+        let (cpixel_len, width, height) =
+            (self.cpixel_len, self.width, self.height);
+        let previous_palette_len = self.previous_palette_len;
+
+        let (subencoding, rest) = u8p().parse(input.clone())?;
+        match subencoding {
+            TRLE_RAW => parse_variant(
+                TrleRaw::parser(cpixel_len, width, height), Raw, rest),
+            TRLE_SOLID => parse_variant(
+                TrleSolid::parser(cpixel_len), Solid, rest),
+            palette_len @ 2..=16 => parse_variant(
+                TrlePackedPalette::parser(palette_len as usize, cpixel_len,
+                                          width, height),
+                PackedPalette, rest),
+            TRLE_REUSED_PACKED_PALETTE if previous_palette_len > 0 =>
+                parse_variant(
+                    TrleReusedPackedPalette::parser(previous_palette_len,
+                                                    width, height),
+                    ReusedPackedPalette, rest),
+            TRLE_PLAIN_RLE => parse_variant(
+                TrlePlainRle::parser(cpixel_len, width, height),
+                PlainRle, rest),
+            TRLE_REUSED_PALETTE_RLE if previous_palette_len > 0 =>
+                parse_variant(
+                    TrleReusedPaletteRle::parser(width, height),
+                    ReusedPaletteRle, rest),
+            palette_len @ 130..=255 => parse_variant(
+                TrlePaletteRle::parser(palette_len as usize - 128,
+                                       cpixel_len, width, height),
+                PaletteRle, rest),
+            d => Err((ParseError::InvalidDiscriminator(d as u64), input))
+        }
+    }
+    fn write<O>(&self, output : &mut O, tile : TrleTile) -> WriteResult
+        where O : Output
+    {
+        use self::TrleTile::*;
+        let (cpixel_len, width, height) =
+            (self.cpixel_len, self.width, self.height);
+
+        match tile {
+            Raw(raw) => {
+                u8p().write(output, TRLE_RAW)?;
+                TrleRaw::parser(cpixel_len, width, height).write(output, raw)
+            },
+            Solid(solid) => {
+                u8p().write(output, TRLE_SOLID)?;
+                TrleSolid::parser(cpixel_len).write(output, solid)
+            },
+            PackedPalette(packed) => {
+                let palette_len = packed.palette.len() / cpixel_len;
+                u8p().write(output, palette_len as u8)?;
+                TrlePackedPalette::parser(palette_len, cpixel_len,
+                                          width, height)
+                    .write(output, packed)
+            },
+            ReusedPackedPalette(packed) => {
+                u8p().write(output, TRLE_REUSED_PACKED_PALETTE)?;
+                TrleReusedPackedPalette::parser(self.previous_palette_len,
+                                                width, height)
+                    .write(output, packed)
+            },
+            PlainRle(rle) => {
+                u8p().write(output, TRLE_PLAIN_RLE)?;
+                TrlePlainRle::parser(cpixel_len, width, height)
+                    .write(output, rle)
+            },
+            PaletteRle(rle) => {
+                let palette_len = rle.palette.len() / cpixel_len;
+                u8p().write(output, 128 + palette_len as u8)?;
+                TrlePaletteRle::parser(palette_len, cpixel_len, width, height)
+                    .write(output, rle)
+            },
+            ReusedPaletteRle(rle) => {
+                u8p().write(output, TRLE_REUSED_PALETTE_RLE)?;
+                TrleReusedPaletteRle::parser(width, height).write(output, rle)
+            }
+        }
+    }
+}
+
+//positions and sizes of subrectangles are packed into the nibbles of a
+//byte, sizes minus one
+fn nibbles(offset : usize) -> impl Parser<T = (usize, usize)> {
+    conv(u8p(),
+         move |n| ((n >> 4) as usize + offset, (n & 0x0f) as usize + offset),
+         move |(a, b)| if a < offset || b < offset
+                          || a - offset > 15 || b - offset > 15 {
+             Err(WriteError::ConversionFailed("does not fit into a nibble"))
+         } else {
+             Ok(((a - offset) << 4 | (b - offset)) as u8)
+         })
+}
+
+packet! { HextileRaw(pixel_len : usize, width : usize, height : usize):
+    [pixels : [bytes_with_len(pixel_len * width * height)] -> Vec<u8>]
+}
+packet! { HextileSubrect:
+    [position : [nibbles(0)] -> (usize, usize)]
+    [size : [nibbles(1)] -> (usize, usize)]
+}
+packet! { HextileColouredSubrect(pixel_len : usize):
+    [color : [bytes_with_len(pixel_len)] -> Vec<u8>]
+    [position : [nibbles(0)] -> (usize, usize)]
+    [size : [nibbles(1)] -> (usize, usize)]
+}
+
+const HEXTILE_RAW : u8 = 1;
+const HEXTILE_BACKGROUND_SPECIFIED : u8 = 2;
+const HEXTILE_FOREGROUND_SPECIFIED : u8 = 4;
+const HEXTILE_ANY_SUBRECTS : u8 = 8;
+const HEXTILE_SUBRECTS_COLOURED : u8 = 16;
+
+#[derive(Debug, Clone)]
+pub enum HextileSubrects {
+    Foreground(Vec<HextileSubrect>),
+    Coloured(Vec<HextileColouredSubrect>)
+}
+//colours that are not specified are those of the previous tile
+#[derive(Debug, Clone)]
+pub struct HextileEncoded {
+    pub background : Option<Vec<u8>>,
+    pub foreground : Option<Vec<u8>>,
+    pub subrects : Option<HextileSubrects>
+}
+#[derive(Debug, Clone)]
+pub enum HextileTile {
+    Raw(HextileRaw),
+    Encoded(HextileEncoded)
+}
+impl HextileTile {
+    pub fn parser(pixel_len : usize, width : usize, height : usize)
+        -> impl Parser<T = Self>
+    {
+        HextileTileParser {
+            pixel_len: pixel_len,
+            width: width,
+            height: height
+        }
+    }
+}
+parameterized_packet_impl! {
+    HextileTile(pixel_len : usize, width : usize, height : usize)
+}
+
+fn parse_if<'a, P, I>(condition : bool, parser : P, input : I)
+    -> ParseResult<Option<P::T>, I>
+    where P : Parser,
+          I : Input<'a>
+{
+    if !condition {
+        return Ok((None, input));
+    }
+    let (value, rest) = parser.parse(input)?;
+    Ok((Some(value), rest))
+}
+
+struct HextileTileParser {
+    pixel_len : usize,
+    width : usize,
+    height : usize
+}
+impl Parser for HextileTileParser {
+    type T = HextileTile;
+    fn parse<'a, I>(&self, input : I) -> ParseResult<HextileTile, I>
+        where I : Input<'a>
+    {
+        let pixel_len = self.pixel_len;
+
+        let (flags, rest) = u8p().parse(input)?;
+        if flags & HEXTILE_RAW != 0 {
+            return parse_variant(
+                HextileRaw::parser(pixel_len, self.width, self.height),
+                HextileTile::Raw, rest);
+        }
+        let (background, rest) = parse_if(
+            flags & HEXTILE_BACKGROUND_SPECIFIED != 0,
+            bytes_with_len(pixel_len), rest)?;
+        let (foreground, rest) = parse_if(
+            flags & HEXTILE_FOREGROUND_SPECIFIED != 0,
+            bytes_with_len(pixel_len), rest)?;
+        let any_subrects = flags & HEXTILE_ANY_SUBRECTS != 0;
+        let (subrects, rest) = if flags & HEXTILE_SUBRECTS_COLOURED != 0 {
+            let (subrects, rest) = parse_if(any_subrects, prefix_len_array(
+                    u8p(), HextileColouredSubrect::parser(pixel_len)), rest)?;
+            (subrects.map(HextileSubrects::Coloured), rest)
+        } else {
+            let (subrects, rest) = parse_if(any_subrects, prefix_len_array(
+                    u8p(), HextileSubrect::parser()), rest)?;
+            (subrects.map(HextileSubrects::Foreground), rest)
+        };
+        Ok((HextileTile::Encoded(HextileEncoded {
+            background: background,
+            foreground: foreground,
+            subrects: subrects
+        }), rest))
+    }
+    fn write<O>(&self, output : &mut O, tile : HextileTile) -> WriteResult
+        where O : Output
+    {
+        let pixel_len = self.pixel_len;
+
+        let encoded = match tile {
+            HextileTile::Raw(raw) => {
+                u8p().write(output, HEXTILE_RAW)?;
+                return HextileRaw::parser(pixel_len, self.width, self.height)
+                    .write(output, raw);
+            },
+            HextileTile::Encoded(encoded) => encoded
+        };
+        let mut flags = 0;
+        if encoded.background.is_some() {
+            flags |= HEXTILE_BACKGROUND_SPECIFIED;
+        }
+        if encoded.foreground.is_some() {
+            flags |= HEXTILE_FOREGROUND_SPECIFIED;
+        }
+        match encoded.subrects {
+            Some(HextileSubrects::Foreground(_)) =>
+                flags |= HEXTILE_ANY_SUBRECTS,
+            Some(HextileSubrects::Coloured(_)) =>
+                flags |= HEXTILE_ANY_SUBRECTS | HEXTILE_SUBRECTS_COLOURED,
+            None => {}
+        }
+        u8p().write(output, flags)?;
+        if let Some(background) = encoded.background {
+            bytes_with_len(pixel_len).write(output, background)?;
+        }
+        if let Some(foreground) = encoded.foreground {
+            bytes_with_len(pixel_len).write(output, foreground)?;
+        }
+        match encoded.subrects {
+            Some(HextileSubrects::Foreground(subrects)) =>
+                prefix_len_array(u8p(), HextileSubrect::parser())
+                    .write(output, subrects),
+            Some(HextileSubrects::Coloured(subrects)) =>
+                prefix_len_array(u8p(),
+                                 HextileColouredSubrect::parser(pixel_len))
+                    .write(output, subrects),
+            None => Ok(())
+        }
+    }
+}
+
+const ZLIB_HEX_ZLIB_RAW : u8 = 32;
+const ZLIB_HEX_ZLIB : u8 = 64;
+
+//ZlibHex has one zlib stream for raw tiles and one for the other ones,
+//which then contain everything after the subencoding of a Hextile tile
+#[derive(Debug, Clone)]
+pub enum ZlibHexTile {
+    Hextile(HextileTile),
+    ZlibRaw(Vec<u8>),
+    Zlib {
+        flags : u8,
+        data : Vec<u8>
+    }
+}
+impl ZlibHexTile {
+    pub fn parser(pixel_len : usize, width : usize, height : usize)
+        -> impl Parser<T = Self>
+    {
+        ZlibHexTileParser {
+            hextile: HextileTileParser {
+                pixel_len: pixel_len,
+                width: width,
+                height: height
+            }
+        }
+    }
+}
+parameterized_packet_impl! {
+    ZlibHexTile(pixel_len : usize, width : usize, height : usize)
+}
+
+fn zlib_hex_data() -> impl Parser<T = Vec<u8>> {
+    dep(length(u16_be()), bytes())
+}
+
+struct ZlibHexTileParser {
+    hextile : HextileTileParser
+}
+impl Parser for ZlibHexTileParser {
+    type T = ZlibHexTile;
+    fn parse<'a, I>(&self, input : I) -> ParseResult<ZlibHexTile, I>
+        where I : Input<'a>
+    {
+        let (flags, rest) = u8p().parse(input.clone())?;
+        if flags & ZLIB_HEX_ZLIB_RAW != 0 {
+            parse_variant(zlib_hex_data(), ZlibHexTile::ZlibRaw, rest)
+        } else if flags & ZLIB_HEX_ZLIB != 0 {
+            parse_variant(zlib_hex_data(), |data| ZlibHexTile::Zlib {
+                flags: flags & !ZLIB_HEX_ZLIB,
+                data: data
+            }, rest)
+        } else {
+            let (tile, rest) = self.hextile.parse(input)?;
+            Ok((ZlibHexTile::Hextile(tile), rest))
+        }
+    }
+    fn write<O>(&self, output : &mut O, tile : ZlibHexTile) -> WriteResult
+        where O : Output
+    {
+        match tile {
+            ZlibHexTile::Hextile(tile) => self.hextile.write(output, tile),
+            ZlibHexTile::ZlibRaw(data) => {
+                u8p().write(output, ZLIB_HEX_ZLIB_RAW)?;
+                zlib_hex_data().write(output, data)
+            },
+            ZlibHexTile::Zlib { flags, data } => {
+                u8p().write(output, flags | ZLIB_HEX_ZLIB)?;
+                zlib_hex_data().write(output, data)
+            }
+        }
+    }
+}
 
 tagged_meta_packet! { RectanglePayload: i32_be() => i32 =>
     [ENCODING_RAW] RawRectangle,
+    [ENCODING_COPY_RECT] CopyRectRectangle,
+    [ENCODING_RRE] RreRectangle,
+    [ENCODING_CORRE] CorreRectangle,
+    [ENCODING_HEXTILE] HextileRectangle,
+    [ENCODING_ZLIB] ZlibRectangle,
+    [ENCODING_ZLIB_HEX] ZlibHexRectangle,
     [ENCODING_TIGHT] TightRectangle,
+    [ENCODING_TIGHT_PNG] TightPngRectangle,
+    [ENCODING_TRLE] TrleRectangle,
+    [ENCODING_ZRLE] ZrleRectangle,
     [ENCODING_DESKTOP_SIZE] DesktopSizeRectangle,
     [ENCODING_CURSOR] CursorRectangle,
     [ENCODING_EXTENDED_DESKTOP_SIZE] ExtendedDesktopSizeRectangle,
+    [ENCODING_DESKTOP_NAME] DesktopNameRectangle,
     [ENCODING_LAST_RECT] LastRectangle
 }
 
@@ -356,13 +1078,134 @@ packet! { FramebufferUpdate:
     [no_of_rectangles : [u16_be()] -> u16]
 }
 
+packet! { Colour:
+    [red : [u16_be()] -> u16]
+    [green : [u16_be()] -> u16]
+    [blue : [u16_be()] -> u16]
+}
+packet! { SetColourMapEntries:
+    [ignored : [ignored(1)] -> ()]
+    [first_colour : [length(u16_be())] -> usize]
+    [colours : [prefix_len_array(u16_be(), Colour::parser())] -> Vec<Colour>]
+}
+
 packet! { Bell:
     [ignored : [nothing()] -> ()]
 }
 
+pub const CLIPBOARD_TEXT : u32 = 1 << 0;
+pub const CLIPBOARD_RTF : u32 = 1 << 1;
+pub const CLIPBOARD_HTML : u32 = 1 << 2;
+pub const CLIPBOARD_FORMATS : u32 = 0xffff;
+
+pub const CLIPBOARD_CAPS : u32 = 1 << 24;
+pub const CLIPBOARD_REQUEST : u32 = 1 << 25;
+pub const CLIPBOARD_PEEK : u32 = 1 << 26;
+pub const CLIPBOARD_NOTIFY : u32 = 1 << 27;
+pub const CLIPBOARD_PROVIDE : u32 = 1 << 28;
+
+//the formats are in the lower bits of the flags, the action in the upper ones
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExtendedClipboard {
+    //the other actions that are supported and the maximum size of each
+    //format, in the order of the format bits
+    Caps {
+        flags : u32,
+        max_sizes : Vec<u32>
+    },
+    Request(u32),
+    Peek,
+    Notify(u32),
+    //the data is a zlib stream with a length and the bytes of each format
+    Provide {
+        formats : u32,
+        data : Vec<u8>
+    }
+}
+
+//extended clipboard messages have a negative length
+#[derive(Debug, Clone, PartialEq)]
+pub enum CutText {
+    Latin1(String),
+    Extended(ExtendedClipboard)
+}
+
+struct CutTextParser;
+impl Parser for CutTextParser {
+    type T = CutText;
+    fn parse<'a, I>(&self, input : I) -> ParseResult<CutText, I>
+        where I : Input<'a>
+    {
+        use self::ExtendedClipboard::*;
+
+        let (length, rest) = i32_be().parse(input)?;
+        if length >= 0 {
+            let (string, rest) =
+                latin1().parse_with_params(rest, length as usize)?;
+            return Ok((CutText::Latin1(string), rest));
+        }
+        let (flags, rest) = u32_be().parse(rest)?;
+        let payload_len = (-(length as i64) as usize).saturating_sub(4);
+        let (payload, rest) = bytes_with_len(payload_len).parse(rest)?;
+        let message = if flags & CLIPBOARD_CAPS != 0 {
+            Caps {
+                flags: flags & !CLIPBOARD_CAPS,
+                max_sizes: payload.chunks(4).filter(|size| size.len() == 4)
+                    .map(|size| size.iter().fold(
+                            0, |value, &byte| value << 8 | byte as u32))
+                    .collect()
+            }
+        } else if flags & CLIPBOARD_REQUEST != 0 {
+            Request(flags & CLIPBOARD_FORMATS)
+        } else if flags & CLIPBOARD_PEEK != 0 {
+            Peek
+        } else if flags & CLIPBOARD_NOTIFY != 0 {
+            Notify(flags & CLIPBOARD_FORMATS)
+        } else if flags & CLIPBOARD_PROVIDE != 0 {
+            Provide {
+                formats: flags & CLIPBOARD_FORMATS,
+                data: payload
+            }
+        } else {
+            return Err((ParseError::PredicateFailed(
+                        "extended clipboard message without action"), rest));
+        };
+        Ok((CutText::Extended(message), rest))
+    }
+    fn write<O>(&self, output : &mut O, text : CutText) -> WriteResult
+        where O : Output
+    {
+        use self::ExtendedClipboard::*;
+
+        let message = match text {
+            CutText::Latin1(string) =>
+                return dep(length(u32_be()), latin1()).write(output, string),
+            CutText::Extended(message) => message
+        };
+        let (flags, max_sizes, data) = match message {
+            Caps { flags, max_sizes } =>
+                (flags | CLIPBOARD_CAPS, max_sizes, Vec::new()),
+            Request(formats) =>
+                (formats | CLIPBOARD_REQUEST, Vec::new(), Vec::new()),
+            Peek => (CLIPBOARD_PEEK, Vec::new(), Vec::new()),
+            Notify(formats) =>
+                (formats | CLIPBOARD_NOTIFY, Vec::new(), Vec::new()),
+            Provide { formats, data } =>
+                (formats | CLIPBOARD_PROVIDE, Vec::new(), data)
+        };
+        let length = 4 + max_sizes.len() * 4 + data.len();
+        i32_be().write(output, -(length as i32))?;
+        u32_be().write(output, flags)?;
+        for size in max_sizes {
+            u32_be().write(output, size)?;
+        }
+        bytes().write(output, data)
+    }
+}
+
 packet! { ServerCutText:
     [ignored : [ignored(3)] -> ()]
-    [string : [dep(length(u32_be()), utf8())] -> String]
+    [text : [CutTextParser] -> CutText]
 }
 
 packet! { EndOfContinuousUpdates:
@@ -371,6 +1214,7 @@ packet! { EndOfContinuousUpdates:
 
 tagged_meta_packet! { ServerToClient: u8p() => u8 =>
     [0] FramebufferUpdate,
+    [1] SetColourMapEntries,
     [2] Bell,
     [3] ServerCutText,
     [150] EndOfContinuousUpdates,
@@ -428,3 +1272,300 @@ mod the_compact_length_parser {
         }
     }
 }
+
+#[cfg(test)]
+mod the_rre_subrect_parser {
+    use super::*;
+    use protocol::parsing::parser_test::*;
+
+    fn rre_subrect(x : usize, y : usize, width : usize, height : usize)
+        -> RreSubrect
+    {
+        RreSubrect {
+            color: vec![1, 2, 3, 0],
+            x: x,
+            y: y,
+            width: width,
+            height: height
+        }
+    }
+
+    fn should_be_able_to_parse_from_itself(subrect : RreSubrect) {
+        let output = write(&RreSubrect::parser(4), subrect.clone()).unwrap();
+        let parsed = parse(&RreSubrect::parser(4), &output[..]).unwrap();
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", subrect));
+    }
+
+    #[test]
+    fn should_encode_the_bounds_of_rre_subrects_with_16_bits_each() {
+        assert_eq!(write(&RreSubrect::parser(4), rre_subrect(1, 2, 3, 4))
+                   .unwrap(),
+                   [1, 2, 3, 0,  0, 1,  0, 2,  0, 3,  0, 4]);
+        should_be_able_to_parse_from_itself(rre_subrect(1, 2, 3, 4));
+        should_be_able_to_parse_from_itself(rre_subrect(65535, 256, 0, 1));
+    }
+
+    #[test]
+    fn should_encode_the_bounds_of_corre_subrects_with_8_bits_each() {
+        let subrect = parse(&CorreSubrect::parser(4),
+                            &[1, 2, 3, 0,  255, 1,  2, 254][..]).unwrap();
+        assert_eq!((subrect.x, subrect.y, subrect.width, subrect.height),
+                   (255, 1, 2, 254));
+        assert_eq!(write(&CorreSubrect::parser(4), subrect).unwrap(),
+                   [1, 2, 3, 0,  255, 1,  2, 254]);
+    }
+}
+
+#[cfg(test)]
+mod the_run_length_parser {
+    use super::*;
+    use protocol::parsing::parser_test::*;
+
+    #[test]
+    fn should_add_up_bytes_until_one_is_not_255() {
+        assert_eq!(parse(&run_length(), &[0][..]).unwrap(), 1);
+        assert_eq!(parse(&run_length(), &[255, 255, 3][..]).unwrap(), 514);
+        assert_eq!(write(&run_length(), 1).unwrap(), [0]);
+        assert_eq!(write(&run_length(), 256).unwrap(), [255, 0]);
+        assert_eq!(write(&run_length(), 514).unwrap(), [255, 255, 3]);
+    }
+
+    #[test]
+    fn should_write_single_palette_indices_without_a_run_length() {
+        assert_eq!(write(&palette_run(), PaletteRun {
+            index: 5,
+            length: 1
+        }).unwrap(), [5]);
+        assert_eq!(write(&palette_run(), PaletteRun {
+            index: 5,
+            length: 300
+        }).unwrap(), [0x85, 255, 44]);
+        assert_eq!(parse(&palette_run(), &[0x85, 255, 44][..]).unwrap(),
+                   PaletteRun {
+                       index: 5,
+                       length: 300
+                   });
+    }
+}
+
+#[cfg(test)]
+mod the_trle_tile_parser {
+    use super::*;
+    use protocol::parsing::parser_test::*;
+
+    fn should_be_able_to_parse_from_itself(tile : TrleTile,
+                                           previous_palette_len : usize)
+        -> Vec<u8>
+    {
+        let parser = TrleTile::parser(3, 2, 2, previous_palette_len);
+        let output = write(&parser, tile.clone()).unwrap();
+        let parsed = parse(&parser, &output[..]).unwrap();
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", tile));
+        output
+    }
+
+    #[test]
+    fn should_take_the_palette_size_from_the_subencoding() {
+        let output = should_be_able_to_parse_from_itself(
+            TrleTile::PackedPalette(TrlePackedPalette {
+                palette: vec![1, 2, 3,  4, 5, 6,  7, 8, 9],
+                indices: vec![0b0110_0000, 0b1000_0000]
+            }), 0);
+        assert_eq!(output[0], 3);
+
+        let output = should_be_able_to_parse_from_itself(
+            TrleTile::PaletteRle(TrlePaletteRle {
+                palette: vec![1, 2, 3,  4, 5, 6],
+                runs: vec![PaletteRun { index: 1, length: 3 },
+                           PaletteRun { index: 0, length: 1 }]
+            }), 0);
+        assert_eq!(output[0], 130);
+    }
+
+    #[test]
+    fn should_parse_runs_until_the_tile_is_full() {
+        let tile = parse(&TrleTile::parser(3, 2, 2, 0),
+                         &[128,  1, 2, 3, 2,  4, 5, 6, 0,  9][..]).unwrap();
+        match tile {
+            TrleTile::PlainRle(rle) => {
+                assert_eq!(rle.runs.len(), 2);
+                assert_eq!(rle.runs[0].length, 3);
+                assert_eq!(rle.runs[1].color, [4, 5, 6]);
+            },
+            _ => assert!(false)
+        }
+        should_be_able_to_parse_from_itself(
+            TrleTile::Raw(TrleRaw {
+                pixels: (0..12).collect()
+            }), 0);
+        should_be_able_to_parse_from_itself(
+            TrleTile::Solid(TrleSolid {
+                color: vec![1, 2, 3]
+            }), 0);
+    }
+
+    #[test]
+    fn should_only_accept_reused_palettes_if_there_was_one_before() {
+        should_be_able_to_parse_from_itself(
+            TrleTile::ReusedPackedPalette(TrleReusedPackedPalette {
+                indices: vec![0b0001_0000, 0b0010_0000]
+            }), 5);
+        match parse(&TrleTile::parser(3, 2, 2, 0), &[127, 0, 0][..])
+            .unwrap_err().0
+        {
+            ParseError::InvalidDiscriminator(d) => assert_eq!(d, 127),
+            _ => assert!(false)
+        }
+        match parse(&TrleTile::parser(3, 2, 2, 0), &[129, 0x83][..])
+            .unwrap_err().0
+        {
+            ParseError::InvalidDiscriminator(d) => assert_eq!(d, 129),
+            _ => assert!(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod the_hextile_tile_parser {
+    use super::*;
+    use protocol::parsing::parser_test::*;
+
+    #[test]
+    fn should_read_only_the_parts_that_the_flags_announce() {
+        let parser = HextileTile::parser(1, 4, 4);
+        let tile = parse(&parser, &[4 | 8,  7,  2,  0x12, 0x30,  0x00, 0x00]
+                         [..]).unwrap();
+        match tile {
+            HextileTile::Encoded(HextileEncoded {
+                background: None,
+                foreground: Some(ref foreground),
+                subrects: Some(HextileSubrects::Foreground(ref subrects))
+            }) => {
+                assert_eq!(*foreground, [7]);
+                assert_eq!(subrects.len(), 2);
+                assert_eq!(subrects[0].position, (1, 2));
+                assert_eq!(subrects[0].size, (4, 1));
+            },
+            _ => assert!(false)
+        }
+        //the other flags do not matter for raw tiles
+        let mut raw = vec![1 | 2 | 8];
+        raw.extend(0..16);
+        match parse(&parser, &raw[..]).unwrap() {
+            HextileTile::Raw(raw) => assert_eq!(raw.pixels.len(), 16),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn should_be_able_to_parse_coloured_subrects_from_itself() {
+        let parser = HextileTile::parser(2, 16, 16);
+        let tile = HextileTile::Encoded(HextileEncoded {
+            background: Some(vec![1, 2]),
+            foreground: None,
+            subrects: Some(HextileSubrects::Coloured(vec![
+                HextileColouredSubrect {
+                    color: vec![3, 4],
+                    position: (15, 0),
+                    size: (1, 16)
+                }]))
+        });
+        let output = write(&parser, tile.clone()).unwrap();
+        assert_eq!(output, [2 | 8 | 16,  1, 2,  1,  3, 4, 0xf0, 0x0f]);
+        let parsed = parse(&parser, &output[..]).unwrap();
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", tile));
+    }
+}
+
+#[cfg(test)]
+mod the_zlib_hex_tile_parser {
+    use super::*;
+    use protocol::parsing::parser_test::*;
+
+    #[test]
+    fn should_read_compressed_data_only_if_a_zlib_flag_is_set() {
+        let parser = ZlibHexTile::parser(1, 2, 2);
+        match parse(&parser, &[32 | 1,  0, 2,  9, 9][..]).unwrap() {
+            ZlibHexTile::ZlibRaw(data) => assert_eq!(data, [9, 9]),
+            _ => assert!(false)
+        }
+        match parse(&parser, &[64 | 2 | 8,  0, 1,  9][..]).unwrap() {
+            ZlibHexTile::Zlib { flags, data } => {
+                assert_eq!(flags, 2 | 8);
+                assert_eq!(data, [9]);
+            },
+            _ => assert!(false)
+        }
+        match parse(&parser, &[2,  7][..]).unwrap() {
+            ZlibHexTile::Hextile(HextileTile::Encoded(encoded)) =>
+                assert_eq!(encoded.background, Some(vec![7])),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn should_be_able_to_parse_from_itself() {
+        let parser = ZlibHexTile::parser(1, 2, 2);
+        let tile = ZlibHexTile::Zlib {
+            flags: 4 | 8 | 16,
+            data: vec![1, 2, 3]
+        };
+        let output = write(&parser, tile.clone()).unwrap();
+        assert_eq!(output, [64 | 4 | 8 | 16,  0, 3,  1, 2, 3]);
+        let parsed = parse(&parser, &output[..]).unwrap();
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", tile));
+    }
+}
+
+#[cfg(test)]
+mod the_cut_text_parser {
+    use super::*;
+    use protocol::parsing::parser_test::*;
+
+    #[test]
+    fn should_read_latin1_text_if_the_length_is_not_negative() {
+        assert_eq!(parse(&CutTextParser, &[0, 0, 0, 2,  b'a', 0xe9][..])
+                   .unwrap(), CutText::Latin1(String::from("a\u{e9}")));
+    }
+
+    #[test]
+    fn should_read_extended_clipboard_messages_if_the_length_is_negative() {
+        let caps = [0xff, 0xff, 0xff, 0xf4,  0x01, 0, 0, 0x05,
+                    0, 0, 0x10, 0,  0, 0, 0, 0x20];
+        assert_eq!(parse(&CutTextParser, &caps[..]).unwrap(),
+                   CutText::Extended(ExtendedClipboard::Caps {
+                       flags: CLIPBOARD_TEXT | CLIPBOARD_HTML,
+                       max_sizes: vec![0x1000, 0x20]
+                   }));
+        let notify = [0xff, 0xff, 0xff, 0xfc,  0x08, 0, 0, 0x03];
+        assert_eq!(parse(&CutTextParser, &notify[..]).unwrap(),
+                   CutText::Extended(ExtendedClipboard::Notify(
+                           CLIPBOARD_TEXT | CLIPBOARD_RTF)));
+        let provide = [0xff, 0xff, 0xff, 0xfa,  0x10, 0, 0, 0x01,  7, 8];
+        assert_eq!(parse(&CutTextParser, &provide[..]).unwrap(),
+                   CutText::Extended(ExtendedClipboard::Provide {
+                       formats: CLIPBOARD_TEXT,
+                       data: vec![7, 8]
+                   }));
+    }
+
+    #[test]
+    fn should_be_able_to_parse_from_itself() {
+        for text in vec![
+            CutText::Latin1(String::from("text")),
+            CutText::Extended(ExtendedClipboard::Caps {
+                flags: CLIPBOARD_TEXT | CLIPBOARD_PROVIDE,
+                max_sizes: vec![1024]
+            }),
+            CutText::Extended(ExtendedClipboard::Request(CLIPBOARD_HTML)),
+            CutText::Extended(ExtendedClipboard::Peek),
+            CutText::Extended(ExtendedClipboard::Provide {
+                formats: CLIPBOARD_TEXT,
+                data: vec![1, 2, 3]
+            })]
+        {
+            let bytes = write(&CutTextParser, text.clone()).unwrap();
+            assert_eq!(parse(&CutTextParser, &bytes[..]).unwrap(), text);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+// This file is part of flashvnc, a VNC client.
+// Copyright 2018 Patrick Plagwitz
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use MainError;
+
+const DISPLAY_BASE_PORT : u16 = 5900;
+//like in other VNC viewers, larger numbers are ports
+const MAX_DISPLAY : u16 = 99;
+
+#[derive(Debug, PartialEq)]
+pub enum Target {
+    Tcp {
+        host : String,
+        port : u16
+    },
+    Unix(PathBuf)
+}
+
+fn error(target : &str, reason : &str) -> MainError {
+    MainError::new(format!("Invalid target {}: {}", target, reason))
+}
+
+fn parse_number(target : &str, number : &str) -> Result<u16, MainError> {
+    u16::from_str(number).map_err(|_| error(target,
+        &format!("{} is not a valid display or port number", number)))
+}
+
+fn display_or_port(target : &str, number : &str) -> Result<u16, MainError> {
+    let n = parse_number(target, number)?;
+    Ok(if n <= MAX_DISPLAY { DISPLAY_BASE_PORT + n } else { n })
+}
+
+//splits off a host in brackets (an IPv6 address) or up to the first colon
+fn split_host(target : &str, s : &str) -> Result<(String, String), MainError>
+{
+    let (host, rest) = if s.starts_with("[") {
+        let end = s.find("]").ok_or_else(||
+            error(target, "missing ] after IPv6 address"))?;
+        (&s[1..end], &s[end + 1..])
+    } else {
+        match s.find(":") {
+            Some(colon) => (&s[..colon], &s[colon..]),
+            None => (s, "")
+        }
+    };
+    if host.is_empty() {
+        return Err(error(target, "host is missing"));
+    }
+    Ok((String::from(host), String::from(rest)))
+}
+
+//host, host:display, host::port, [v6addr]:display, unix:/path
+//and vnc://host:port
+pub fn parse_target(target : &str) -> Result<Target, MainError> {
+    if target.starts_with("unix:") {
+        let path = &target["unix:".len()..];
+        if path.is_empty() {
+            return Err(error(target, "socket path is missing"));
+        }
+        return Ok(Target::Unix(PathBuf::from(path)));
+    }
+
+    if target.starts_with("vnc://") {
+        let rest = &target["vnc://".len()..];
+        let rest = rest.trim_end_matches("/");
+        let (host, rest) = split_host(target, rest)?;
+        let port = if rest.is_empty() {
+            DISPLAY_BASE_PORT
+        } else if rest.starts_with(":") {
+            parse_number(target, &rest[1..])?
+        } else {
+            return Err(error(target, "expected : after host"));
+        };
+        return Ok(Target::Tcp { host: host, port: port });
+    }
+
+    let (host, rest) = split_host(target, target)?;
+    let port = if rest.is_empty() {
+        DISPLAY_BASE_PORT
+    } else if rest.starts_with("::") {
+        parse_number(target, &rest[2..])?
+    } else if rest.get(1..).map_or(false, |r| r.contains(":")) {
+        return Err(error(target, "IPv6 addresses must be enclosed in [ and ]"));
+    } else if rest.starts_with(":") {
+        display_or_port(target, &rest[1..])?
+    } else {
+        return Err(error(target, "expected : after host"));
+    };
+    Ok(Target::Tcp { host: host, port: port })
+}
+
+#[cfg(test)]
+mod the_target_parser {
+    use super::*;
+
+    fn tcp(host : &str, port : u16) -> Target {
+        Target::Tcp {
+            host: String::from(host),
+            port: port
+        }
+    }
+
+    #[test]
+    fn should_use_display_0_if_only_a_host_is_given() {
+        assert_eq!(parse_target("localhost").unwrap(), tcp("localhost", 5900));
+    }
+
+    #[test]
+    fn should_add_small_display_numbers_to_5900() {
+        assert_eq!(parse_target("host:1").unwrap(), tcp("host", 5901));
+        assert_eq!(parse_target("host:99").unwrap(), tcp("host", 5999));
+    }
+
+    #[test]
+    fn should_treat_large_display_numbers_as_ports() {
+        assert_eq!(parse_target("host:5902").unwrap(), tcp("host", 5902));
+        assert_eq!(parse_target("10.0.0.1:100").unwrap(), tcp("10.0.0.1", 100));
+    }
+
+    #[test]
+    fn should_take_the_number_after_two_colons_as_a_port() {
+        assert_eq!(parse_target("host::5").unwrap(), tcp("host", 5));
+        assert_eq!(parse_target("host::5901").unwrap(), tcp("host", 5901));
+    }
+
+    #[test]
+    fn should_accept_ipv6_addresses_in_brackets() {
+        assert_eq!(parse_target("[::1]").unwrap(), tcp("::1", 5900));
+        assert_eq!(parse_target("[::1]:2").unwrap(), tcp("::1", 5902));
+        assert_eq!(parse_target("[fe80::1]:5910").unwrap(),
+                   tcp("fe80::1", 5910));
+        assert_eq!(parse_target("[fe80::1]::22").unwrap(), tcp("fe80::1", 22));
+    }
+
+    #[test]
+    fn should_accept_paths_of_unix_sockets() {
+        assert_eq!(parse_target("unix:/tmp/vnc.sock").unwrap(),
+                   Target::Unix(PathBuf::from("/tmp/vnc.sock")));
+    }
+
+    #[test]
+    fn should_accept_vnc_urls_with_real_ports() {
+        assert_eq!(parse_target("vnc://host").unwrap(), tcp("host", 5900));
+        assert_eq!(parse_target("vnc://host:1").unwrap(), tcp("host", 1));
+        assert_eq!(parse_target("vnc://host:5901/").unwrap(),
+                   tcp("host", 5901));
+        assert_eq!(parse_target("vnc://[::1]:5901").unwrap(),
+                   tcp("::1", 5901));
+    }
+
+    #[test]
+    fn should_return_errors_for_malformed_targets() {
+        for &target in ["", "host:", "host:x", "host::", "host::70000",
+                        ":1", "[::1", "[::1]x", "[::1]\u{e9}", "::1",
+                        "fe80::1:2", "unix:", "vnc://", "vnc://host:"].iter()
+        {
+            assert!(parse_target(target).is_err(), "{}", target);
+        }
+    }
+
+    #[test]
+    fn should_name_the_target_in_errors() {
+        let MainError(message, _) = parse_target("host:x").unwrap_err();
+        assert_eq!(message, "Invalid target host:x: \
+                             x is not a valid display or port number");
+    }
+}
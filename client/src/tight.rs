@@ -324,3 +324,151 @@ pub mod jpeg {
         ];
     }
 }
+
+pub mod png {
+    use std::ffi::CStr;
+    use std::ptr;
+    use libc::{c_char,c_int,c_void,size_t};
+
+    const PNG_IMAGE_VERSION : u32 = 1;
+    const PNG_FORMAT_RGB : u32 = 2;
+
+    //png_image of the simplified API of libpng 1.6
+    #[repr(C)]
+    struct PngImage {
+        opaque : *mut c_void,
+        version : u32,
+        width : u32,
+        height : u32,
+        format : u32,
+        flags : u32,
+        colormap_entries : u32,
+        warning_or_error : u32,
+        message : [c_char; 64]
+    }
+
+    extern "C" {
+        fn png_image_begin_read_from_memory(image : *mut PngImage,
+                                            memory : *const c_void,
+                                            size : size_t) -> c_int;
+        fn png_image_finish_read(image : *mut PngImage,
+                                 background : *const c_void,
+                                 buffer : *mut c_void,
+                                 row_stride : i32,
+                                 colormap : *mut c_void) -> c_int;
+        fn png_image_free(image : *mut PngImage);
+    }
+
+    pub struct Image {
+        pub width : usize,
+        pub height : usize,
+        pub rgb : Vec<u8>
+    }
+
+    //png_image_free can be called any number of times
+    fn error(image : &mut PngImage) -> String {
+        unsafe {
+            png_image_free(image);
+        }
+        let message = unsafe { CStr::from_ptr(image.message.as_ptr()) };
+        format!("Invalid PNG: {}", message.to_string_lossy())
+    }
+
+    //the size is checked before anything is allocated for the pixels
+    pub fn decode(src : &[u8], expected_width : usize,
+                  expected_height : usize) -> Result<Image, String>
+    {
+        let mut image = PngImage {
+            opaque: ptr::null_mut(),
+            version: PNG_IMAGE_VERSION,
+            width: 0,
+            height: 0,
+            format: 0,
+            flags: 0,
+            colormap_entries: 0,
+            warning_or_error: 0,
+            message: [0; 64]
+        };
+        let ret = unsafe {
+            png_image_begin_read_from_memory(
+                &mut image, src.as_ptr() as *const c_void, src.len())
+        };
+        if ret == 0 {
+            return Err(error(&mut image));
+        }
+
+        image.format = PNG_FORMAT_RGB;
+        let width = image.width as usize;
+        let height = image.height as usize;
+        if width != expected_width || height != expected_height {
+            unsafe {
+                png_image_free(&mut image);
+            }
+            return Err(format!(
+                "PNG of size {}x{} does not fit rectangle of size {}x{}",
+                width, height, expected_width, expected_height));
+        }
+        let len = match width.checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(3))
+        {
+            Some(len) => len,
+            None => {
+                unsafe {
+                    png_image_free(&mut image);
+                }
+                return Err(format!("PNG of size {}x{} is too large",
+                                   width, height));
+            }
+        };
+        let mut rgb = vec![0u8; len];
+        //also cleans up the image
+        let ret = unsafe {
+            png_image_finish_read(
+                &mut image, ptr::null(), rgb.as_mut_ptr() as *mut c_void,
+                0, ptr::null_mut())
+        };
+        if ret == 0 {
+            return Err(error(&mut image));
+        }
+
+        Ok(Image {
+            width: width,
+            height: height,
+            rgb: rgb
+        })
+    }
+
+    #[cfg(test)]
+    mod the_png_decoder {
+        use super::*;
+
+        const TWO_BY_ONE_PNG : &'static [u8] =
+            include_bytes!("../tests/two_by_one.png");
+
+        #[test]
+        fn should_decode_rgb_pngs() {
+            let image = decode(TWO_BY_ONE_PNG, 2, 1).unwrap();
+            assert_eq!(image.width, 2);
+            assert_eq!(image.height, 1);
+            assert_eq!(image.rgb, [255, 0, 0, 0, 0, 255]);
+        }
+
+        #[test]
+        fn should_return_an_error_upon_getting_invalid_input() {
+            let error = match decode(&[0x0, 0x1][..], 2, 1) {
+                Ok(_) => panic!("no error"),
+                Err(e) => e
+            };
+            assert!(error.contains("Invalid PNG"));
+        }
+
+        #[test]
+        fn should_refuse_pngs_of_another_size_than_expected() {
+            let error = match decode(TWO_BY_ONE_PNG, 1, 2) {
+                Ok(_) => panic!("no error"),
+                Err(e) => e
+            };
+            assert!(error.contains("does not fit"));
+        }
+    }
+}
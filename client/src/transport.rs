@@ -0,0 +1,405 @@
+// This file is part of flashvnc, a VNC client.
+// Copyright 2018 Patrick Plagwitz
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std;
+use std::io;
+use std::io::{Read,Write};
+use std::fs::File;
+use std::net::TcpStream;
+use std::os::unix::io::{AsRawFd,FromRawFd,IntoRawFd,RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::process::{Child,Command,Stdio};
+use std::sync::{Arc,Mutex};
+use std::sync::atomic::{AtomicBool,Ordering};
+use std::time::Duration;
+
+use libc;
+use openssl::ssl::{SslConnector,SslContext,SslMethod,SslStream,SslVerifyMode,
+                   SslVersion,Ssl,ErrorCode,HandshakeError};
+
+use MainError;
+
+//a byte stream to the server that the read end and (a clone of it) the
+//write end can use at the same time
+pub trait Transport : Read + Write + Send {
+    fn try_clone(&self) -> io::Result<Box<Transport>>;
+    fn set_nonblocking(&self, nonblocking : bool) -> io::Result<()>;
+    //for reading and for writing
+    fn raw_fds(&self) -> (RawFd, RawFd);
+
+    fn disable_nagles_algo(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for TcpStream {
+    fn try_clone(&self) -> io::Result<Box<Transport>> {
+        Ok(Box::new(TcpStream::try_clone(self)?))
+    }
+    fn set_nonblocking(&self, nonblocking : bool) -> io::Result<()> {
+        TcpStream::set_nonblocking(self, nonblocking)
+    }
+    fn raw_fds(&self) -> (RawFd, RawFd) {
+        (self.as_raw_fd(), self.as_raw_fd())
+    }
+
+    fn disable_nagles_algo(&self) -> io::Result<()> {
+        let one : libc::c_int = 1;
+        let ret;
+        unsafe {
+            ret = libc::setsockopt(
+                self.as_raw_fd(), libc::SOL_TCP, libc::TCP_NODELAY,
+                &one as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t);
+        }
+        if ret != 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Transport for UnixStream {
+    fn try_clone(&self) -> io::Result<Box<Transport>> {
+        Ok(Box::new(UnixStream::try_clone(self)?))
+    }
+    fn set_nonblocking(&self, nonblocking : bool) -> io::Result<()> {
+        UnixStream::set_nonblocking(self, nonblocking)
+    }
+    fn raw_fds(&self) -> (RawFd, RawFd) {
+        (self.as_raw_fd(), self.as_raw_fd())
+    }
+}
+
+fn set_fd_nonblocking(fd : RawFd, nonblocking : bool) -> io::Result<()> {
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let flags = if nonblocking {
+            flags | libc::O_NONBLOCK
+        } else {
+            flags & !libc::O_NONBLOCK
+        };
+        if libc::fcntl(fd, libc::F_SETFL, flags) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+const CHILD_EXIT_GRACE_PERIOD_MS : u64 = 500;
+const CHILD_EXIT_POLL_INTERVAL_MS : u64 = 10;
+
+//waits for the command once all clones of the transport are gone, which
+//closes its stdin, and kills it if it does not exit by itself soon
+struct ChildGuard(Child);
+impl Drop for ChildGuard {
+    fn drop(&mut self) {
+        for _ in 0..(CHILD_EXIT_GRACE_PERIOD_MS / CHILD_EXIT_POLL_INTERVAL_MS) {
+            match self.0.try_wait() {
+                Ok(None) => std::thread::sleep(
+                    Duration::from_millis(CHILD_EXIT_POLL_INTERVAL_MS)),
+                _ => return
+            }
+        }
+        self.0.kill().ok();
+        self.0.wait().ok();
+    }
+}
+
+//the RFB stream goes through stdin and stdout of a command like ssh -W
+pub struct CommandTransport {
+    stdout : File,
+    stdin : File,
+    _child : Arc<ChildGuard>
+}
+impl CommandTransport {
+    pub fn spawn(command : &str) -> io::Result<Self> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap().into_raw_fd();
+        let stdout = child.stdout.take().unwrap().into_raw_fd();
+        Ok(Self {
+            stdout: unsafe { File::from_raw_fd(stdout) },
+            stdin: unsafe { File::from_raw_fd(stdin) },
+            _child: Arc::new(ChildGuard(child))
+        })
+    }
+}
+impl Read for CommandTransport {
+    fn read(&mut self, buf : &mut [u8]) -> io::Result<usize> {
+        self.stdout.read(buf)
+    }
+}
+impl Write for CommandTransport {
+    fn write(&mut self, buf : &[u8]) -> io::Result<usize> {
+        self.stdin.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdin.flush()
+    }
+}
+impl Transport for CommandTransport {
+    fn try_clone(&self) -> io::Result<Box<Transport>> {
+        Ok(Box::new(CommandTransport {
+            stdout: self.stdout.try_clone()?,
+            stdin: self.stdin.try_clone()?,
+            _child: self._child.clone()
+        }))
+    }
+    fn set_nonblocking(&self, nonblocking : bool) -> io::Result<()> {
+        set_fd_nonblocking(self.stdout.as_raw_fd(), nonblocking)?;
+        set_fd_nonblocking(self.stdin.as_raw_fd(), nonblocking)
+    }
+    fn raw_fds(&self) -> (RawFd, RawFd) {
+        (self.stdout.as_raw_fd(), self.stdin.as_raw_fd())
+    }
+}
+
+//lets one thread wake the other one from poll when it has changed the
+//state of the SslStream, e.g. consumed the data that the other one was
+//waiting for, so that neither has to poll with a timeout
+struct Wakeup {
+    waiting : AtomicBool,
+    read_fd : RawFd,
+    write_fd : RawFd
+}
+impl Wakeup {
+    fn new() -> io::Result<Self> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let wakeup = Self {
+            waiting: AtomicBool::new(false),
+            read_fd: fds[0],
+            write_fd: fds[1]
+        };
+        set_fd_nonblocking(wakeup.read_fd, true)?;
+        set_fd_nonblocking(wakeup.write_fd, true)?;
+        Ok(wakeup)
+    }
+
+    //both are called with the stream locked, so no notification is lost
+    fn prepare_to_wait(&self) {
+        self.waiting.store(true, Ordering::SeqCst);
+    }
+    fn notify(&self) {
+        if self.waiting.swap(false, Ordering::SeqCst) {
+            let byte = [0u8];
+            //a full pipe already wakes the other thread
+            unsafe {
+                libc::write(self.write_fd,
+                            byte.as_ptr() as *const libc::c_void, 1);
+            }
+        }
+    }
+
+    fn wait_for(&self, fd : RawFd, events : libc::c_short)
+        -> io::Result<()>
+    {
+        let mut poll_fds = [
+            libc::pollfd {
+                fd: fd,
+                events: events,
+                revents: 0
+            },
+            libc::pollfd {
+                fd: self.read_fd,
+                events: libc::POLLIN,
+                revents: 0
+            }];
+        let ret = unsafe { libc::poll(poll_fds.as_mut_ptr(), 2, -1) };
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+        let mut buf = [0u8; 16];
+        while unsafe {
+            libc::read(self.read_fd, buf.as_mut_ptr() as *mut libc::c_void,
+                       buf.len())
+        } > 0 { }
+        Ok(())
+    }
+}
+impl Drop for Wakeup {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.read_fd);
+            libc::close(self.write_fd);
+        }
+    }
+}
+
+//the SslStream is shared between the reading and the writing thread,
+//so the underlying transport is non-blocking and the lock is only held
+//while OpenSSL works on its buffers
+pub struct TlsTransport {
+    stream : Arc<Mutex<SslStream<Box<Transport>>>>,
+    fds : (RawFd, RawFd),
+    //of the reading and the writing thread
+    wakeups : Arc<(Wakeup, Wakeup)>
+}
+impl TlsTransport {
+    fn new(stream : SslStream<Box<Transport>>) -> io::Result<Self> {
+        stream.get_ref().set_nonblocking(true)?;
+        let fds = stream.get_ref().raw_fds();
+        Ok(Self {
+            stream: Arc::new(Mutex::new(stream)),
+            fds: fds,
+            wakeups: Arc::new((Wakeup::new()?, Wakeup::new()?))
+        })
+    }
+
+    //returns None if the operation should be retried
+    fn handle_error<T>(&self, err : ::openssl::ssl::Error, eof_value : T,
+                       wakeup : &Wakeup)
+        -> Option<io::Result<T>>
+    {
+        let code = err.code();
+        if code == ErrorCode::WANT_READ {
+            wakeup.wait_for(self.fds.0, libc::POLLIN).err().map(Err)
+        } else if code == ErrorCode::WANT_WRITE {
+            wakeup.wait_for(self.fds.1, libc::POLLOUT).err().map(Err)
+        } else if code == ErrorCode::ZERO_RETURN {
+            Some(Ok(eof_value))
+        } else {
+            Some(Err(match err.into_io_error() {
+                Ok(io_error) => io_error,
+                Err(err) => if err.code() == ErrorCode::SYSCALL {
+                    return Some(Ok(eof_value));
+                } else {
+                    io::Error::new(io::ErrorKind::Other, err)
+                }
+            }))
+        }
+    }
+}
+impl Read for TlsTransport {
+    fn read(&mut self, buf : &mut [u8]) -> io::Result<usize> {
+        let (ref own, ref other) = *self.wakeups;
+        loop {
+            let result = {
+                let mut stream = self.stream.lock().unwrap();
+                let result = stream.ssl_read(buf);
+                if result.is_err() {
+                    own.prepare_to_wait();
+                }
+                other.notify();
+                result
+            };
+            match result {
+                Ok(n) => return Ok(n),
+                Err(err) => if let Some(ret) = self.handle_error(err, 0, own) {
+                    return ret;
+                }
+            }
+        }
+    }
+}
+impl Write for TlsTransport {
+    fn write(&mut self, buf : &[u8]) -> io::Result<usize> {
+        let (ref other, ref own) = *self.wakeups;
+        loop {
+            let result = {
+                let mut stream = self.stream.lock().unwrap();
+                let result = stream.ssl_write(buf);
+                if result.is_err() {
+                    own.prepare_to_wait();
+                }
+                other.notify();
+                result
+            };
+            match result {
+                Ok(n) => return Ok(n),
+                Err(err) => if let Some(ret) = self.handle_error(err, 0, own) {
+                    return ret;
+                }
+            }
+        }
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+impl Transport for TlsTransport {
+    fn try_clone(&self) -> io::Result<Box<Transport>> {
+        Ok(Box::new(TlsTransport {
+            stream: self.stream.clone(),
+            fds: self.fds,
+            wakeups: self.wakeups.clone()
+        }))
+    }
+    fn set_nonblocking(&self, _nonblocking : bool) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Other,
+                           "TLS transport is always non-blocking"))
+    }
+    fn raw_fds(&self) -> (RawFd, RawFd) {
+        self.fds
+    }
+
+    fn disable_nagles_algo(&self) -> io::Result<()> {
+        self.stream.lock().unwrap().get_ref().disable_nagles_algo()
+    }
+}
+
+fn handshake_error(err : HandshakeError<Box<Transport>>) -> MainError {
+    MainError::new(format!("TLS handshake failed: {}", match err {
+        HandshakeError::SetupFailure(err) => err.to_string(),
+        HandshakeError::Failure(stream)
+            | HandshakeError::WouldBlock(stream) =>
+            stream.error().to_string()
+    }))
+}
+
+//anonymous Diffie-Hellman, so there is no certificate to check
+pub fn connect_anonymous_tls(transport : Box<Transport>)
+    -> Result<TlsTransport, MainError>
+{
+    let mut context = SslContext::builder(SslMethod::tls())?;
+    context.set_verify(SslVerifyMode::NONE);
+    context.set_cipher_list("aNULL:@SECLEVEL=0")?;
+    //there are no anonymous cipher suites in TLS 1.3
+    context.set_max_proto_version(Some(SslVersion::TLS1_2))?;
+    let ssl = Ssl::new(&context.build())?;
+
+    let stream = ssl.connect(transport).map_err(handshake_error)?;
+    Ok(TlsTransport::new(stream)?)
+}
+
+pub fn connect_x509_tls(transport : Box<Transport>, host : &str,
+                        ca_file : Option<&Path>)
+    -> Result<TlsTransport, MainError>
+{
+    let mut connector = SslConnector::builder(SslMethod::tls())?;
+    if let Some(ca_file) = ca_file {
+        connector.set_ca_file(ca_file)?;
+    }
+
+    let stream = connector.build().connect(host, transport)
+        .map_err(handshake_error)?;
+    Ok(TlsTransport::new(stream)?)
+}
@@ -22,11 +22,15 @@ extern crate spectral;
 
 //std::sync::Once
 extern crate flashvnc;
+extern crate openssl;
+extern crate tempdir;
+extern crate flate2;
 
 use spectral::prelude::*;
 
 use std::net::{TcpListener,TcpStream,Shutdown};
-use std::io::Write;
+use std::os::unix::net::UnixListener;
+use std::io::{Read,Write};
 use std::cell::RefCell;
 use std::time::Duration;
 use std::sync::atomic::{AtomicU16,Ordering};
@@ -34,7 +38,19 @@ use std::thread::JoinHandle;
 use std::sync::mpsc;
 
 use flashvnc::protocol::rfb;
-use flashvnc::protocol::parsing::Packet;
+use flashvnc::protocol::parsing::{Packet,ParameterizedPacket};
+
+use openssl::ssl::{SslAcceptor,SslContext,SslMethod,SslStream,Ssl,
+                   SslVersion,SslFiletype};
+use openssl::x509::{X509,X509NameBuilder};
+use openssl::x509::extension::SubjectAlternativeName;
+use openssl::pkey::PKey;
+use openssl::rsa::Rsa;
+use openssl::bn::BigNum;
+use openssl::asn1::Asn1Time;
+use openssl::hash::MessageDigest;
+use tempdir::TempDir;
+use std::path::PathBuf;
 
 static PORT : AtomicU16 = AtomicU16::new(0);
 
@@ -45,6 +61,28 @@ fn server_string(port : u16) -> String {
     format!("localhost:{}", port)
 }
 
+fn config(host : &str, port : u16) -> flashvnc::ConnectionConfig {
+    flashvnc::ConnectionConfig {
+        host: String::from(host),
+        port: port,
+        benchmark: false,
+        throttle: false,
+        username: None,
+        password: None,
+        x509_ca_file: None,
+        tls_server_name: None,
+        listen: None,
+        proxy_command: None,
+        unix_socket: None,
+        reconnect: false,
+        view_only: false,
+        shared: true,
+        depth: flashvnc::PixelDepth::TrueColour24,
+        bell: flashvnc::BellMode::Audible,
+        tight_png: false
+    }
+}
+
 fn socketpair(port : u16) -> (TcpStream, TcpStream) {
     let address = server_string(port);
     let address_clone = address.clone();
@@ -59,8 +97,42 @@ fn socketpair(port : u16) -> (TcpStream, TcpStream) {
     (server, client)
 }
 
+fn server_init(name : &str) -> rfb::ServerInit {
+    rfb::ServerInit {
+        width: 4,
+        height: 4,
+        pixel_format: rfb::PixelFormat {
+            bits_per_pixel: 32,
+            depth: 24,
+            big_endian: false,
+            true_color: true,
+            red_max: 255,
+            green_max: 255,
+            blue_max: 255,
+            red_shift: 16,
+            green_shift: 8,
+            blue_shift: 0
+        },
+        name: String::from(name)
+    }
+}
+
 struct View {
-    events_in : Option<mpsc::Receiver<flashvnc::GuiEvent>>
+    events_in : Option<mpsc::Receiver<flashvnc::GuiEvent>>,
+    output : ViewOutput
+}
+impl View {
+    fn new(events_in : mpsc::Receiver<flashvnc::GuiEvent>)
+        -> (Self, mpsc::Receiver<flashvnc::ProtocolEvent>)
+    {
+        let (events_out, protocol_events) = mpsc::channel();
+        (View {
+            events_in: Some(events_in),
+            output: ViewOutput {
+                events_out: events_out
+            }
+        }, protocol_events)
+    }
 }
 impl flashvnc::View for View {
     type Output = ViewOutput;
@@ -68,46 +140,50 @@ impl flashvnc::View for View {
         self.events_in.take().unwrap()
     }
     fn get_output(&self) -> &ViewOutput {
-        unimplemented!()
+        &self.output
     }
     fn desired_pixel_format() -> flashvnc::PixelFormat {
         flashvnc::PixelFormat::NativeBgrx
     }
 }
 #[derive(Clone)]
-struct ViewOutput;
+struct ViewOutput {
+    events_out : mpsc::Sender<flashvnc::ProtocolEvent>
+}
 impl flashvnc::ViewOutput for ViewOutput {
-    fn handle_event(&self, _event : flashvnc::ProtocolEvent) {
-        unimplemented!()
+    fn handle_event(&self, event : flashvnc::ProtocolEvent) {
+        self.events_out.send(event).unwrap_or(());
     }
-    fn update_framebuffer_sync(&self, _fb_data : Vec<u8>,
-                               _size : flashvnc::FbSize) {
-        unimplemented!()
+    fn update_framebuffer_sync(&self, fb_data : Vec<u8>,
+                               size : flashvnc::FbSize) {
+        self.handle_event(flashvnc::ProtocolEvent::UpdateFramebuffer(
+                fb_data, size));
     }
 }
 
 struct Client {
     socket : TcpStream,
     thread : JoinHandle<Result<(), flashvnc::MainError>>,
-    _gui_events : mpsc::Sender<flashvnc::GuiEvent>,
+    gui_events : mpsc::Sender<flashvnc::GuiEvent>,
+    protocol_events : mpsc::Receiver<flashvnc::ProtocolEvent>,
     _server_port : u16
 }
 impl Client {
     fn launch() -> Self {
+        Self::launch_with(|_| { })
+    }
+    fn launch_with<F>(modify_config : F) -> Self
+        where F : FnOnce(&mut flashvnc::ConnectionConfig) + Send + 'static
+    {
         let port = new_port();
 
         let (gui_events, gui_events_receiver) = mpsc::channel();
-        let view = View {
-            events_in: Some(gui_events_receiver)
-        };
+        let (view, protocol_events) = View::new(gui_events_receiver);
         let thread = std::thread::spawn(move || {
             std::thread::sleep(Duration::from_millis(200));
-            flashvnc::socket_thread_main(flashvnc::ConnectionConfig {
-                host: String::from("localhost"),
-                port: port,
-                benchmark: false,
-                throttle: false
-            }, view)
+            let mut config = config("localhost", port);
+            modify_config(&mut config);
+            flashvnc::socket_thread_main(config, view)
         });
 
         let server = TcpListener::bind(&server_string(port)).unwrap();
@@ -117,7 +193,8 @@ impl Client {
         Client {
             socket: client,
             thread: thread,
-            _gui_events: gui_events,
+            gui_events: gui_events,
+            protocol_events: protocol_events,
             _server_port: port
         }
     }
@@ -137,6 +214,80 @@ impl Client {
         }.write(&mut self.socket).unwrap();
         //TODO read response and return?
     }
+
+    fn offer_security_types(&mut self, types : Vec<u8>) {
+        rfb::SecurityTypes::SecurityTypesArray(rfb::SecurityTypesArray {
+            types: types
+        }).write(&mut self.socket).unwrap();
+    }
+
+    //up to the point where the client is waiting for server messages;
+    //returns the pixel format that the client asked for
+    fn complete_handshake(&mut self, buffer : &RefCell<Vec<u8>>)
+        -> rfb::PixelFormat
+    {
+        self.negotiate_version();
+        self.read_bytes(rfb::PROTOCOL_VERSION_LEN);
+        self.offer_security_types(vec![rfb::SEC_TYPE_NONE]);
+        assert_eq!(self.read_bytes(1), [rfb::SEC_TYPE_NONE]);
+        rfb::SecurityResult::Ok(rfb::SecurityResultOk { })
+            .write(&mut self.socket).unwrap();
+        rfb::ClientInit::parse(buffer, &self.socket).unwrap();
+
+        server_init("desktop").write(&mut self.socket).unwrap();
+        let mut pixel_format = None;
+        loop {
+            match self.next_message(buffer) {
+                rfb::ClientToServer::SetPixelFormat(message) => {
+                    pixel_format = Some(message.format);
+                },
+                rfb::ClientToServer::FramebufferUpdateRequest(_) => break,
+                _ => { }
+            }
+        }
+        pixel_format.expect("should set the pixel format")
+    }
+
+    fn next_message(&self, buffer : &RefCell<Vec<u8>>)
+        -> rfb::ClientToServer
+    {
+        rfb::ClientToServer::parse(buffer, &self.socket).unwrap()
+    }
+
+    //skips events until one of them is picked
+    fn next_event_matching<T, F>(&self, mut pick : F) -> T
+        where F : FnMut(flashvnc::ProtocolEvent) -> Option<T>
+    {
+        loop {
+            let event = self.protocol_events
+                .recv_timeout(Duration::from_secs(2))
+                .expect("should send the expected protocol event");
+            if let Some(picked) = pick(event) {
+                return picked;
+            }
+        }
+    }
+
+    //the whole framebuffer in the native BGRX format of the test view
+    fn next_framebuffer(&self) -> Vec<u8> {
+        self.next_event_matching(|event| match event {
+            flashvnc::ProtocolEvent::UpdateFramebuffer(fb, _) => Some(fb),
+            _ => None
+        })
+    }
+
+    fn next_clipboard_text(&self) -> String {
+        self.next_event_matching(|event| match event {
+            flashvnc::ProtocolEvent::SetClipboardText(text) => Some(text),
+            _ => None
+        })
+    }
+
+    fn read_bytes(&mut self, len : usize) -> Vec<u8> {
+        let mut bytes = vec![0; len];
+        self.socket.read_exact(&mut bytes[..]).unwrap();
+        bytes
+    }
 }
 
 #[test]
@@ -174,18 +325,243 @@ fn should_stop_communication_upon_getting_invalid_string_encoding() {
 }
 
 #[test]
-fn should_not_continue_with_rfb_versions_other_than_3_8() {
+fn should_negotiate_down_to_the_rfb_version_of_the_server() {
+    for &(server_version, negotiated_version) in [
+        ("RFB 003.007\n", "RFB 003.007\n"),
+        ("RFB 003.003\n", "RFB 003.003\n"),
+        ("RFB 003.005\n", "RFB 003.003\n"),
+        ("RFB 003.889\n", "RFB 003.008\n"),
+        ("RFB 004.001\n", "RFB 003.008\n")].iter()
+    {
+        let mut client = Client::launch();
+
+        rfb::ProtocolVersion {
+            string: String::from(server_version)
+        }.write(&mut client.socket).unwrap();
+
+        let buffer = RefCell::new(Vec::new());
+        let response = rfb::ProtocolVersion::parse(&buffer, &client.socket)
+            .unwrap();
+        assert_eq!(response.string, negotiated_version);
+
+        client.join();
+    }
+}
+
+#[test]
+fn should_not_continue_with_rfb_versions_older_than_3_3() {
     let mut client = Client::launch();
 
     rfb::ProtocolVersion {
-        string: String::from("RFB 003.007\n")
+        string: String::from("RFB 003.002\n")
     }.write(&mut client.socket).unwrap();
 
     client.socket.shutdown(Shutdown::Both).unwrap();
 
+    let error_message = client.should_exit_with_error();
+    assert_that!(error_message).contains("Unsupported RFB version");
+    assert_that!(error_message).contains("003.002");
+}
+
+#[test]
+fn should_not_continue_if_the_server_sends_no_rfb_version() {
+    let mut client = Client::launch();
+
+    client.socket.write_all(b"HTTP/1.1 400").unwrap();
+
+    client.socket.shutdown(Shutdown::Both).unwrap();
+
     let error_message = client.should_exit_with_error();
     assert_that!(error_message).contains("ProtocolVersion");
-    assert_that!(error_message).contains("RFB version 3.8");
+    assert_that!(error_message).contains("RFB version");
+}
+
+#[test]
+fn should_not_expect_a_security_result_for_no_authentication_in_rfb_3_7() {
+    let mut client = Client::launch();
+
+    rfb::ProtocolVersion::new(rfb::Version::V3_7)
+        .write(&mut client.socket).unwrap();
+    client.read_bytes(rfb::PROTOCOL_VERSION_LEN);
+    client.offer_security_types(vec![rfb::SEC_TYPE_NONE]);
+    assert_eq!(client.read_bytes(1), [rfb::SEC_TYPE_NONE]);
+
+    assert_eq!(client.read_bytes(1), [1]);
+
+    client.join();
+}
+
+#[test]
+fn should_let_the_server_choose_the_security_type_in_rfb_3_3() {
+    let mut client = Client::launch_with(|config| {
+        config.password = Some(String::from("secret"));
+    });
+
+    rfb::ProtocolVersion::new(rfb::Version::V3_3)
+        .write(&mut client.socket).unwrap();
+    client.read_bytes(rfb::PROTOCOL_VERSION_LEN);
+    rfb::LegacySecurityType::Chosen(rfb::ServerSecurityType {
+        sec_type: rfb::SEC_TYPE_VNC as u32
+    }).write(&mut client.socket).unwrap();
+
+    rfb::VncAuthChallenge {
+        challenge: VNC_AUTH_CHALLENGE.to_vec()
+    }.write(&mut client.socket).unwrap();
+    assert_eq!(client.read_bytes(VNC_AUTH_CHALLENGE.len()), VNC_AUTH_RESPONSE);
+    rfb::LegacySecurityResult::Failed(rfb::SecurityResultFailed { })
+        .write(&mut client.socket).unwrap();
+
+    client.socket.shutdown(Shutdown::Write).unwrap();
+
+    let client_error = client.should_exit_with_error();
+    assert_that!(client_error).contains("security handshake");
+}
+
+fn connect_to_listening_client(port : u16) -> TcpStream {
+    for _ in 0..50 {
+        if let Ok(socket) = TcpStream::connect(&server_string(port)) {
+            socket.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+            return socket;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    panic!("client is not listening on port {}", port);
+}
+
+#[test]
+fn should_accept_connections_from_servers_one_after_the_other_when_listening() {
+    let port = new_port();
+    let (_gui_events, gui_events_receiver) = mpsc::channel();
+    let (view, protocol_events) = View::new(gui_events_receiver);
+    std::thread::spawn(move || {
+        let mut config = config("", 0);
+        config.listen = Some(port);
+        flashvnc::socket_thread_main(config, view)
+    });
+
+    for _ in 0..2 {
+        let mut server = connect_to_listening_client(port);
+        rfb::ProtocolVersion {
+            string: String::from("RFB 003.008\n")
+        }.write(&mut server).unwrap();
+
+        let buffer = RefCell::new(Vec::new());
+        let response = rfb::ProtocolVersion::parse(&buffer, &server).unwrap();
+        assert_eq!(response.string, "RFB 003.008\n");
+        server.shutdown(Shutdown::Both).unwrap();
+    }
+
+    match protocol_events.recv().unwrap() {
+        flashvnc::ProtocolEvent::SetTitle(title) =>
+            assert_eq!(title, format!("listening on port {}", port)),
+        _ => panic!("should show that it is listening")
+    }
+}
+
+#[test]
+fn should_talk_to_the_server_through_the_stdin_and_stdout_of_a_proxy_command() {
+    let temp_dir = TempDir::new("flashvnc").unwrap();
+    let received = temp_dir.path().join("received");
+    let (_gui_events, gui_events_receiver) = mpsc::channel();
+    let (view, _protocol_events) = View::new(gui_events_receiver);
+    let mut config = config("", 0);
+    config.proxy_command = Some(format!(
+            "printf 'RFB 003.008\\n'; exec cat > {}",
+            received.to_str().unwrap()));
+
+    let result = std::thread::spawn(move || {
+        flashvnc::socket_thread_main(config, view)
+    }).join().unwrap();
+
+    assert!(result.is_err());
+    let mut contents = String::new();
+    std::fs::File::open(&received).unwrap()
+        .read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "RFB 003.008\n");
+}
+
+#[test]
+fn should_stop_a_proxy_command_that_does_not_exit_by_itself() {
+    let (_gui_events, gui_events_receiver) = mpsc::channel();
+    let (view, _protocol_events) = View::new(gui_events_receiver);
+    let mut config = config("", 0);
+    config.proxy_command = Some(String::from(
+            "printf 'RFB 003.002\\n'; exec sleep 1000"));
+    let (result_sender, result_receiver) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        result_sender.send(flashvnc::socket_thread_main(config, view))
+            .unwrap();
+    });
+
+    let result = result_receiver.recv_timeout(Duration::from_secs(5))
+        .expect("should not wait for the proxy command to exit");
+    assert!(result.is_err());
+}
+
+#[test]
+fn should_connect_to_a_server_on_a_unix_socket() {
+    let temp_dir = TempDir::new("flashvnc").unwrap();
+    let path = temp_dir.path().join("vnc.sock");
+    let listener = UnixListener::bind(&path).unwrap();
+    let (_gui_events, gui_events_receiver) = mpsc::channel();
+    let (view, _protocol_events) = View::new(gui_events_receiver);
+    let mut config = config("localhost", 0);
+    config.unix_socket = Some(path);
+    let thread = std::thread::spawn(move || {
+        flashvnc::socket_thread_main(config, view)
+    });
+
+    let (mut server, _) = listener.accept().unwrap();
+    server.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    rfb::ProtocolVersion {
+        string: String::from("RFB 003.008\n")
+    }.write(&mut server).unwrap();
+    let buffer = RefCell::new(Vec::new());
+    let response = rfb::ProtocolVersion::parse(&buffer, &server).unwrap();
+    assert_eq!(response.string, "RFB 003.008\n");
+
+    rfb::SecurityTypes::SecurityTypesArray(rfb::SecurityTypesArray {
+        types: vec![rfb::SEC_TYPE_NONE]
+    }).write(&mut server).unwrap();
+    let response = rfb::SecurityResponse::parse(&buffer, &server).unwrap();
+    assert_eq!(response.sec_type, rfb::SEC_TYPE_NONE);
+    server.shutdown(Shutdown::Both).unwrap();
+
+    assert!(thread.join().unwrap().is_err());
+}
+
+#[test]
+fn should_reconnect_after_the_connection_breaks_if_asked_to() {
+    let port = new_port();
+    let listener = TcpListener::bind(&server_string(port)).unwrap();
+    let (_gui_events, gui_events_receiver) = mpsc::channel();
+    let (view, protocol_events) = View::new(gui_events_receiver);
+    std::thread::spawn(move || {
+        let mut config = config("localhost", port);
+        config.reconnect = true;
+        flashvnc::socket_thread_main(config, view)
+    });
+
+    for i in 0..2 {
+        let (mut server, _) = listener.accept().unwrap();
+        server.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        rfb::ProtocolVersion {
+            string: String::from("RFB 003.008\n")
+        }.write(&mut server).unwrap();
+        let buffer = RefCell::new(Vec::new());
+        let response = rfb::ProtocolVersion::parse(&buffer, &server).unwrap();
+        assert_eq!(response.string, "RFB 003.008\n");
+        server.shutdown(Shutdown::Both).unwrap();
+
+        if i == 0 {
+            match protocol_events.recv().unwrap() {
+                flashvnc::ProtocolEvent::SetTitle(title) =>
+                    assert_eq!(title, "reconnecting…"),
+                _ => panic!("should show that it is reconnecting")
+            }
+        }
+    }
 }
 
 #[test]
@@ -212,15 +588,10 @@ fn should_stop_and_output_an_error_if_it_cant_write_to_the_server() {
 
     //TODO refactor
     let (_, rx) = mpsc::channel();
-    let view = View {
-        events_in: Some(rx)
-    };
-    let error_message = flashvnc::handle_connection(flashvnc::ConnectionConfig {
-            host: String::new(),
-            port: 0,
-            benchmark: false,
-            throttle: false
-        }, client, view).unwrap_err().0;
+    let (view, _) = View::new(rx);
+    let error_message = flashvnc::handle_connection(config("", 0), 
+                                                    client, view)
+        .unwrap_err().0;
     assert_that!(error_message.to_lowercase()).contains("connection");
     assert_that!(error_message.to_lowercase()).contains("can't write");
     assert!(!error_message.contains("{"));
@@ -279,6 +650,1003 @@ fn should_output_a_server_error_after_security_handshake() {
     assert_that!(client_error).contains(message);
 }
 
+const VNC_AUTH_CHALLENGE : [u8; 16] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+//DES-encrypted challenge with the key derived from "secret"
+const VNC_AUTH_RESPONSE : [u8; 16] = [
+    0xee, 0x22, 0x53, 0x9f, 0x33, 0xa5, 0x98, 0x3e,
+    0xc1, 0x2f, 0x9c, 0x2e, 0xdb, 0xc9, 0x95, 0xdd];
+
+fn authenticate_with_vnc_auth(client : &mut Client) -> bool {
+    client.negotiate_version();
+    client.read_bytes(rfb::PROTOCOL_VERSION_LEN);
+    client.offer_security_types(vec![rfb::SEC_TYPE_VNC]);
+    assert_eq!(client.read_bytes(1), [rfb::SEC_TYPE_VNC]);
+
+    rfb::VncAuthChallenge {
+        challenge: VNC_AUTH_CHALLENGE.to_vec()
+    }.write(&mut client.socket).unwrap();
+    client.read_bytes(VNC_AUTH_CHALLENGE.len()) == VNC_AUTH_RESPONSE
+}
+
+#[test]
+fn should_respond_to_the_vnc_auth_challenge_with_the_encrypted_password() {
+    let mut client = Client::launch_with(|config| {
+        config.password = Some(String::from("secret"));
+    });
+
+    assert!(authenticate_with_vnc_auth(&mut client));
+    rfb::SecurityResult::Ok(rfb::SecurityResultOk { })
+        .write(&mut client.socket).unwrap();
+    assert_eq!(client.read_bytes(1), [1]);
+
+    client.join();
+}
+
+#[test]
+fn should_output_the_server_error_if_the_password_is_wrong() {
+    let message = "Authentication failed";
+    let mut client = Client::launch_with(|config| {
+        config.password = Some(String::from("wrong"));
+    });
+
+    assert!(!authenticate_with_vnc_auth(&mut client));
+    rfb::SecurityResult::Failed(rfb::ErrorReason {
+        string: String::from(message)
+    }).write(&mut client.socket).unwrap();
+
+    client.socket.shutdown(Shutdown::Write).unwrap();
+
+    let client_error = client.should_exit_with_error();
+    assert_that!(client_error).contains("security handshake");
+    assert_that!(client_error).contains(message);
+}
+
+#[test]
+fn should_not_reconnect_if_the_authentication_fails() {
+    let message = "Authentication failed";
+    let mut client = Client::launch_with(|config| {
+        config.password = Some(String::from("wrong"));
+        config.reconnect = true;
+    });
+
+    assert!(!authenticate_with_vnc_auth(&mut client));
+    rfb::SecurityResult::Failed(rfb::ErrorReason {
+        string: String::from(message)
+    }).write(&mut client.socket).unwrap();
+
+    let client_error = client.should_exit_with_error();
+    assert_that!(client_error).contains(message);
+}
+
+#[test]
+fn should_refuse_vnc_authentication_if_no_password_is_given() {
+    let mut client = Client::launch();
+
+    client.negotiate_version();
+    client.offer_security_types(vec![rfb::SEC_TYPE_VNC]);
+
+    client.socket.shutdown(Shutdown::Write).unwrap();
+
+    let error_message = client.should_exit_with_error();
+    assert_that!(error_message).contains("password");
+}
+
+fn start_vencrypt(client : &mut Client, subtype : u32) {
+    client.negotiate_version();
+    client.read_bytes(rfb::PROTOCOL_VERSION_LEN);
+    client.offer_security_types(vec![rfb::SEC_TYPE_VENCRYPT]);
+    assert_eq!(client.read_bytes(1), [rfb::SEC_TYPE_VENCRYPT]);
+
+    rfb::VeNCryptVersion {
+        major: 0,
+        minor: 2
+    }.write(&mut client.socket).unwrap();
+    assert_eq!(client.read_bytes(2), [0, 2]);
+    rfb::VeNCryptStatus {
+        status: 0
+    }.write(&mut client.socket).unwrap();
+
+    rfb::VeNCryptSubtypes {
+        subtypes: vec![subtype]
+    }.write(&mut client.socket).unwrap();
+    let buffer = RefCell::new(Vec::new());
+    let chosen = rfb::VeNCryptSubtypeResponse::parse(&buffer, &client.socket)
+        .unwrap();
+    assert_eq!(chosen.subtype, subtype);
+    rfb::VeNCryptStatus {
+        status: 1
+    }.write(&mut client.socket).unwrap();
+}
+
+fn accept_anonymous_tls(client : &Client) -> SslStream<TcpStream> {
+    let mut context = SslContext::builder(SslMethod::tls()).unwrap();
+    context.set_cipher_list("aNULL:@SECLEVEL=0").unwrap();
+    context.set_max_proto_version(Some(SslVersion::TLS1_2)).unwrap();
+    let ssl = Ssl::new(&context.build()).unwrap();
+    ssl.accept(client.socket.try_clone().unwrap()).unwrap()
+}
+
+struct Certificate {
+    _dir : TempDir,
+    cert_file : PathBuf,
+    key_file : PathBuf
+}
+fn self_signed_certificate_for_localhost() -> Certificate {
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "localhost").unwrap();
+    let name = name.build();
+
+    let mut cert = X509::builder().unwrap();
+    cert.set_version(2).unwrap();
+    cert.set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer()
+                           .unwrap()).unwrap();
+    cert.set_subject_name(&name).unwrap();
+    cert.set_issuer_name(&name).unwrap();
+    cert.set_pubkey(&key).unwrap();
+    cert.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+    cert.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+    let alt_name = SubjectAlternativeName::new().dns("localhost")
+        .build(&cert.x509v3_context(None, None)).unwrap();
+    cert.append_extension(alt_name).unwrap();
+    cert.sign(&key, MessageDigest::sha256()).unwrap();
+    let cert = cert.build();
+
+    let dir = TempDir::new("flashvnc").unwrap();
+    let cert_file = dir.path().join("cert.pem");
+    let key_file = dir.path().join("key.pem");
+    std::fs::File::create(&cert_file).unwrap()
+        .write_all(&cert.to_pem().unwrap()).unwrap();
+    std::fs::File::create(&key_file).unwrap()
+        .write_all(&key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+    Certificate {
+        _dir: dir,
+        cert_file: cert_file,
+        key_file: key_file
+    }
+}
+
+fn x509_acceptor(certificate : &Certificate) -> SslAcceptor {
+    let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls())
+        .unwrap();
+    acceptor.set_certificate_chain_file(&certificate.cert_file).unwrap();
+    acceptor.set_private_key_file(&certificate.key_file, SslFiletype::PEM)
+        .unwrap();
+    acceptor.build()
+}
+
+#[test]
+fn should_do_vnc_authentication_inside_an_anonymous_tls_session() {
+    let mut client = Client::launch_with(|config| {
+        config.password = Some(String::from("secret"));
+    });
+
+    start_vencrypt(&mut client, rfb::VENCRYPT_TLS_VNC);
+    let mut tls = accept_anonymous_tls(&client);
+
+    rfb::VncAuthChallenge {
+        challenge: VNC_AUTH_CHALLENGE.to_vec()
+    }.write(&mut tls).unwrap();
+    let mut response = [0; 16];
+    tls.read_exact(&mut response).unwrap();
+    assert_eq!(response, VNC_AUTH_RESPONSE);
+    rfb::SecurityResult::Ok(rfb::SecurityResultOk { })
+        .write(&mut tls).unwrap();
+    let mut shared_flag = [0];
+    tls.read_exact(&mut shared_flag).unwrap();
+    assert_eq!(shared_flag, [1]);
+
+    client.join();
+}
+
+#[test]
+fn should_send_plain_credentials_after_verifying_the_server_certificate() {
+    let certificate = self_signed_certificate_for_localhost();
+    let cert_file = certificate.cert_file.clone();
+    let mut client = Client::launch_with(move |config| {
+        config.username = Some(String::from("user"));
+        config.password = Some(String::from("secret"));
+        config.x509_ca_file = Some(cert_file);
+    });
+
+    start_vencrypt(&mut client, rfb::VENCRYPT_X509_PLAIN);
+    let mut tls = x509_acceptor(&certificate)
+        .accept(client.socket.try_clone().unwrap()).unwrap();
+
+    let buffer = RefCell::new(Vec::new());
+    let credentials = rfb::PlainAuth::parse(&buffer, &mut tls).unwrap();
+    assert_eq!(credentials.username_and_password, 
+               (String::from("user"), String::from("secret")));
+    rfb::SecurityResult::Ok(rfb::SecurityResultOk { })
+        .write(&mut tls).unwrap();
+    let mut shared_flag = [0];
+    tls.read_exact(&mut shared_flag).unwrap();
+    assert_eq!(shared_flag, [1]);
+
+    client.join();
+}
+
+#[test]
+fn should_check_the_server_certificate_against_the_given_server_name() {
+    let certificate = self_signed_certificate_for_localhost();
+    let cert_file = certificate.cert_file.clone();
+    let mut client = Client::launch_with(move |config| {
+        config.x509_ca_file = Some(cert_file);
+        config.tls_server_name = Some(String::from("example.com"));
+    });
+
+    start_vencrypt(&mut client, rfb::VENCRYPT_X509_NONE);
+    x509_acceptor(&certificate)
+        .accept(client.socket.try_clone().unwrap()).unwrap_err();
+
+    let error_message = client.should_exit_with_error();
+    assert_that!(error_message).contains("certificate");
+}
+
+#[test]
+fn should_not_check_a_server_certificate_without_a_server_name() {
+    let temp_dir = TempDir::new("flashvnc").unwrap();
+    let path = temp_dir.path().join("vnc.sock");
+    let listener = UnixListener::bind(&path).unwrap();
+    let (_gui_events, gui_events_receiver) = mpsc::channel();
+    let (view, _protocol_events) = View::new(gui_events_receiver);
+    let mut config = config("localhost", 0);
+    config.unix_socket = Some(path);
+    let thread = std::thread::spawn(move || {
+        flashvnc::socket_thread_main(config, view)
+    });
+
+    let (mut server, _) = listener.accept().unwrap();
+    server.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let buffer = RefCell::new(Vec::new());
+    rfb::ProtocolVersion {
+        string: String::from("RFB 003.008\n")
+    }.write(&mut server).unwrap();
+    rfb::ProtocolVersion::parse(&buffer, &server).unwrap();
+    rfb::SecurityTypes::SecurityTypesArray(rfb::SecurityTypesArray {
+        types: vec![rfb::SEC_TYPE_VENCRYPT]
+    }).write(&mut server).unwrap();
+    rfb::SecurityResponse::parse(&buffer, &server).unwrap();
+    rfb::VeNCryptVersion {
+        major: 0,
+        minor: 2
+    }.write(&mut server).unwrap();
+    let mut version = [0; 2];
+    server.read_exact(&mut version).unwrap();
+    rfb::VeNCryptStatus {
+        status: 0
+    }.write(&mut server).unwrap();
+    rfb::VeNCryptSubtypes {
+        subtypes: vec![rfb::VENCRYPT_X509_NONE]
+    }.write(&mut server).unwrap();
+    rfb::VeNCryptSubtypeResponse::parse(&buffer, &server).unwrap();
+    rfb::VeNCryptStatus {
+        status: 1
+    }.write(&mut server).unwrap();
+
+    let error_message = thread.join().unwrap()
+        .expect_err("should exit with error").0;
+    assert_that!(error_message).contains("--tls-server-name");
+}
+
+#[test]
+fn should_refuse_a_server_certificate_it_cannot_verify() {
+    let certificate = self_signed_certificate_for_localhost();
+    let mut client = Client::launch();
+
+    start_vencrypt(&mut client, rfb::VENCRYPT_X509_NONE);
+    x509_acceptor(&certificate)
+        .accept(client.socket.try_clone().unwrap()).unwrap_err();
+
+    let error_message = client.should_exit_with_error();
+    assert_that!(error_message).contains("TLS");
+    assert_that!(error_message).contains("certificate");
+}
+
+fn tight_capability(code : u32, vendor : &str, name : &str)
+    -> rfb::TightCapability
+{
+    rfb::TightCapability {
+        code: code,
+        vendor: String::from(vendor),
+        name: String::from(name)
+    }
+}
+
+#[test]
+fn should_negotiate_capabilities_with_tight_security() {
+    let mut client = Client::launch_with(|config| {
+        config.password = Some(String::from("secret"));
+    });
+    let buffer = RefCell::new(Vec::new());
+
+    client.negotiate_version();
+    client.read_bytes(rfb::PROTOCOL_VERSION_LEN);
+    client.offer_security_types(vec![rfb::SEC_TYPE_TIGHT]);
+    assert_eq!(client.read_bytes(1), [rfb::SEC_TYPE_TIGHT]);
+
+    rfb::TightCapabilities {
+        capabilities: vec![tight_capability(0, "TGHT", "NOTUNNEL")]
+    }.write(&mut client.socket).unwrap();
+    let tunnel = rfb::TightCapabilityChoice::parse(&buffer, &client.socket)
+        .unwrap();
+    assert_eq!(tunnel.code, rfb::TIGHT_NO_TUNNEL);
+
+    rfb::TightCapabilities {
+        capabilities: vec![tight_capability(2, "STDV", "VNCAUTH_")]
+    }.write(&mut client.socket).unwrap();
+    let auth = rfb::TightCapabilityChoice::parse(&buffer, &client.socket)
+        .unwrap();
+    assert_eq!(auth.code, rfb::TIGHT_AUTH_VNC);
+    rfb::VncAuthChallenge {
+        challenge: VNC_AUTH_CHALLENGE.to_vec()
+    }.write(&mut client.socket).unwrap();
+    assert_eq!(client.read_bytes(VNC_AUTH_CHALLENGE.len()), VNC_AUTH_RESPONSE);
+    rfb::SecurityResult::Ok(rfb::SecurityResultOk { })
+        .write(&mut client.socket).unwrap();
+    assert_eq!(client.read_bytes(1), [1]);
+
+    server_init("tight desktop").write(&mut client.socket).unwrap();
+    rfb::TightInteractionCapabilities {
+        no_of_server_messages: 1,
+        no_of_client_messages: 0,
+        no_of_encodings: 1
+    }.write(&mut client.socket).unwrap();
+    tight_capability(150, "TGHT", "EOCUMSG_").write(&mut client.socket)
+        .unwrap();
+    tight_capability(7, "TGHT", "TIGHT___").write(&mut client.socket)
+        .unwrap();
+    client.socket.shutdown(Shutdown::Write).unwrap();
+
+    match client.protocol_events.recv().unwrap() {
+        flashvnc::ProtocolEvent::SetTitle(title) => 
+            assert_eq!(title, "tight desktop"),
+        _ => panic!("should set the title first")
+    }
+    let error_message = client.should_exit_with_error();
+    assert_that!(error_message.to_lowercase()).contains("broken");
+}
+
+#[test]
+fn should_refuse_tight_security_without_the_notunnel_capability() {
+    let mut client = Client::launch();
+
+    client.negotiate_version();
+    client.read_bytes(rfb::PROTOCOL_VERSION_LEN);
+    client.offer_security_types(vec![rfb::SEC_TYPE_TIGHT]);
+    assert_eq!(client.read_bytes(1), [rfb::SEC_TYPE_TIGHT]);
+
+    rfb::TightCapabilities {
+        capabilities: vec![tight_capability(1, "SICR", "SCHANNEL")]
+    }.write(&mut client.socket).unwrap();
+
+    let error_message = client.should_exit_with_error();
+    assert_that!(error_message).contains("tunnel");
+}
+
+#[test]
+fn should_not_send_input_events_in_view_only_mode() {
+    let mut client = Client::launch_with(|config| {
+        config.view_only = true;
+    });
+    let buffer = RefCell::new(Vec::new());
+    client.complete_handshake(&buffer);
+
+    client.gui_events.send(flashvnc::GuiEvent::Pointer {
+        state: 1,
+        x: 2,
+        y: 3
+    }).unwrap();
+    client.gui_events.send(flashvnc::GuiEvent::Keyboard {
+        key: 0x61,
+        down: true
+    }).unwrap();
+    client.gui_events.send(flashvnc::GuiEvent::SetEncodingQuality(
+            flashvnc::EncodingQuality::Lossless)).unwrap();
+
+    loop {
+        match client.next_message(&buffer) {
+            rfb::ClientToServer::SetEncodings(_) => break,
+            rfb::ClientToServer::PointerEvent(_)
+                | rfb::ClientToServer::KeyEvent(_) =>
+                panic!("should not send input events"),
+            _ => { }
+        }
+    }
+
+    client.gui_events.send(flashvnc::GuiEvent::SetViewOnly(false)).unwrap();
+    client.gui_events.send(flashvnc::GuiEvent::Keyboard {
+        key: 0x61,
+        down: true
+    }).unwrap();
+    loop {
+        match client.next_message(&buffer) {
+            rfb::ClientToServer::KeyEvent(event) => {
+                assert_eq!(event.key, 0x61);
+                break;
+            },
+            _ => { }
+        }
+    }
+}
+
+#[test]
+fn should_release_pressed_keys_and_buttons_when_switching_to_view_only() {
+    let mut client = Client::launch();
+    let buffer = RefCell::new(Vec::new());
+    client.complete_handshake(&buffer);
+
+    client.gui_events.send(flashvnc::GuiEvent::Keyboard {
+        key: 0x61,
+        down: true
+    }).unwrap();
+    client.gui_events.send(flashvnc::GuiEvent::Pointer {
+        state: 1,
+        x: 2,
+        y: 3
+    }).unwrap();
+    client.gui_events.send(flashvnc::GuiEvent::SetViewOnly(true)).unwrap();
+
+    let mut key_released = false;
+    let mut button_released = false;
+    while !(key_released && button_released) {
+        match client.next_message(&buffer) {
+            rfb::ClientToServer::KeyEvent(event) => if !event.down {
+                assert_eq!(event.key, 0x61);
+                key_released = true;
+            },
+            rfb::ClientToServer::PointerEvent(event) => if event.mask == 0 {
+                assert_eq!((event.x, event.y), (2, 3));
+                button_released = true;
+            },
+            _ => { }
+        }
+    }
+    client.join();
+}
+
+#[test]
+fn should_ask_for_a_shared_session_unless_exclusive_access_is_configured() {
+    for &shared in [true, false].iter() {
+        let mut client = Client::launch_with(move |config| {
+            config.shared = shared;
+        });
+        client.negotiate_version();
+        client.read_bytes(rfb::PROTOCOL_VERSION_LEN);
+        client.offer_security_types(vec![rfb::SEC_TYPE_NONE]);
+        assert_eq!(client.read_bytes(1), [rfb::SEC_TYPE_NONE]);
+        rfb::SecurityResult::Ok(rfb::SecurityResultOk { })
+            .write(&mut client.socket).unwrap();
+
+        assert_eq!(client.read_bytes(1), [shared as u8]);
+        client.join();
+    }
+}
+
+fn zlib_compress(data : &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::ZlibEncoder::new(
+        Vec::new(), flate2::Compression::Default);
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn rgb_at(fb : &[u8], fb_width : usize, x : usize, y : usize) -> [u8; 3] {
+    let i = (y * fb_width + x) * 4;
+    [fb[i + 2], fb[i + 1], fb[i]]
+}
+
+fn tight_basic_rectangle(x : usize, y : usize, width : usize, height : usize,
+                         filter : Option<rfb::TightFilter>) -> rfb::Rectangle
+{
+    let method = match filter {
+        Some(filter) => rfb::TightMethod::Basic(rfb::TightBasicFilterId {
+            control_byte: 0x40,
+            filter: filter
+        }),
+        None => rfb::TightMethod::BasicNoFilterId(rfb::TightBasicNoFilterId {
+            control_byte: 0
+        })
+    };
+    rfb::Rectangle {
+        x: x,
+        y: y,
+        width: width,
+        height: height,
+        payload: rfb::RectanglePayload::TightRectangle(rfb::TightRectangle {
+            control_byte: 0,
+            method: method
+        })
+    }
+}
+
+fn send_fb_update(client : &mut Client, no_of_rectangles : u16) {
+    rfb::ServerToClient::FramebufferUpdate(rfb::FramebufferUpdate {
+        no_of_rectangles: no_of_rectangles
+    }).write(&mut client.socket).unwrap();
+}
+
+fn send_tight_zlib_data(client : &mut Client, data : &[u8]) {
+    let data = zlib_compress(data);
+    rfb::TightZlib {
+        length: data.len()
+    }.write(&mut client.socket).unwrap();
+    client.socket.write_all(&data).unwrap();
+}
+
+#[test]
+fn should_decode_tight_rectangles_with_the_gradient_filter() {
+    let mut client = Client::launch();
+    let buffer = RefCell::new(Vec::new());
+    client.complete_handshake(&buffer);
+
+    send_fb_update(&mut client, 1);
+    tight_basic_rectangle(1, 1, 2, 2, Some(rfb::TightFilter::GradientFilter(
+                rfb::GradientFilter { })))
+        .write(&mut client.socket).unwrap();
+    send_tight_zlib_data(&mut client, &[10, 20, 30,   190, 30, 30,
+                                        190, 60, 60,  6, 0, 0]);
+
+    let fb = client.next_framebuffer();
+    assert_eq!(rgb_at(&fb, 4, 1, 1), [10, 20, 30]);
+    assert_eq!(rgb_at(&fb, 4, 2, 1), [200, 50, 60]);
+    assert_eq!(rgb_at(&fb, 4, 1, 2), [200, 80, 90]);
+    assert_eq!(rgb_at(&fb, 4, 2, 2), [5, 110, 120]);
+}
+
+#[test]
+fn should_decode_tight_rectangles_with_an_explicit_copy_filter() {
+    let mut client = Client::launch();
+    let buffer = RefCell::new(Vec::new());
+    client.complete_handshake(&buffer);
+
+    send_fb_update(&mut client, 1);
+    tight_basic_rectangle(0, 0, 2, 2, Some(rfb::TightFilter::CopyFilter(
+                rfb::CopyFilter { })))
+        .write(&mut client.socket).unwrap();
+    send_tight_zlib_data(&mut client, &[1, 2, 3,     4, 5, 6,
+                                        7, 8, 9,     10, 11, 12]);
+
+    let fb = client.next_framebuffer();
+    assert_eq!(rgb_at(&fb, 4, 0, 0), [1, 2, 3]);
+    assert_eq!(rgb_at(&fb, 4, 1, 0), [4, 5, 6]);
+    assert_eq!(rgb_at(&fb, 4, 0, 1), [7, 8, 9]);
+    assert_eq!(rgb_at(&fb, 4, 1, 1), [10, 11, 12]);
+}
+
+#[test]
+fn should_read_tight_data_shorter_than_12_bytes_without_compression() {
+    let mut client = Client::launch();
+    let buffer = RefCell::new(Vec::new());
+    client.complete_handshake(&buffer);
+
+    send_fb_update(&mut client, 3);
+    tight_basic_rectangle(0, 0, 3, 1, Some(rfb::TightFilter::CopyFilter(
+                rfb::CopyFilter { })))
+        .write(&mut client.socket).unwrap();
+    client.socket.write_all(&[1, 2, 3,  4, 5, 6,  7, 8, 9]).unwrap();
+    tight_basic_rectangle(0, 1, 2, 1, None)
+        .write(&mut client.socket).unwrap();
+    client.socket.write_all(&[10, 11, 12,  13, 14, 15]).unwrap();
+    tight_basic_rectangle(0, 2, 1, 1, Some(rfb::TightFilter::GradientFilter(
+                rfb::GradientFilter { })))
+        .write(&mut client.socket).unwrap();
+    client.socket.write_all(&[16, 17, 18]).unwrap();
+
+    let fb = client.next_framebuffer();
+    assert_eq!(rgb_at(&fb, 4, 0, 0), [1, 2, 3]);
+    assert_eq!(rgb_at(&fb, 4, 2, 0), [7, 8, 9]);
+    assert_eq!(rgb_at(&fb, 4, 0, 1), [10, 11, 12]);
+    assert_eq!(rgb_at(&fb, 4, 1, 1), [13, 14, 15]);
+    assert_eq!(rgb_at(&fb, 4, 0, 2), [16, 17, 18]);
+}
+
+const TWO_BY_ONE_PNG : &'static [u8] = include_bytes!("two_by_one.png");
+
+#[test]
+fn should_ask_for_tight_png_only_if_configured() {
+    for &tight_png in [true, false].iter() {
+        let mut client = Client::launch_with(move |config| {
+            config.tight_png = tight_png;
+        });
+        let buffer = RefCell::new(Vec::new());
+        client.complete_handshake(&buffer);
+
+        client.gui_events.send(flashvnc::GuiEvent::SetEncodingQuality(
+                flashvnc::EncodingQuality::Lossless)).unwrap();
+        loop {
+            match client.next_message(&buffer) {
+                rfb::ClientToServer::SetEncodings(message) => {
+                    assert_eq!(message.encodings
+                               .contains(&rfb::ENCODING_TIGHT_PNG),
+                               tight_png);
+                    break;
+                },
+                _ => { }
+            }
+        }
+        client.join();
+    }
+}
+
+#[test]
+fn should_decode_png_rectangles_of_tight_png() {
+    let mut client = Client::launch_with(|config| {
+        config.tight_png = true;
+    });
+    let buffer = RefCell::new(Vec::new());
+    client.complete_handshake(&buffer);
+
+    send_fb_update(&mut client, 2);
+    rfb::Rectangle {
+        x: 1,
+        y: 2,
+        width: 2,
+        height: 1,
+        payload: rfb::RectanglePayload::TightPngRectangle(
+            rfb::TightPngRectangle {
+                control_byte: 0,
+                method: rfb::TightPngMethod::Png(rfb::TightPng {
+                    control_byte: 0xa0,
+                    length: TWO_BY_ONE_PNG.len()
+                })
+            })
+    }.write(&mut client.socket).unwrap();
+    client.socket.write_all(TWO_BY_ONE_PNG).unwrap();
+    rfb::Rectangle {
+        x: 0,
+        y: 0,
+        width: 1,
+        height: 1,
+        payload: rfb::RectanglePayload::TightPngRectangle(
+            rfb::TightPngRectangle {
+                control_byte: 0,
+                method: rfb::TightPngMethod::Fill(rfb::TightFill {
+                    control_byte: 0x80
+                })
+            })
+    }.write(&mut client.socket).unwrap();
+    client.socket.write_all(&[1, 2, 3]).unwrap();
+
+    let fb = client.next_framebuffer();
+    assert_eq!(rgb_at(&fb, 4, 1, 2), [255, 0, 0]);
+    assert_eq!(rgb_at(&fb, 4, 2, 2), [0, 0, 255]);
+    assert_eq!(rgb_at(&fb, 4, 0, 0), [1, 2, 3]);
+}
+
+fn zrle_rectangle(x : usize, y : usize, width : usize, height : usize,
+                  length : usize) -> rfb::Rectangle
+{
+    rfb::Rectangle {
+        x: x,
+        y: y,
+        width: width,
+        height: height,
+        payload: rfb::RectanglePayload::ZrleRectangle(rfb::ZrleRectangle {
+            length: length
+        })
+    }
+}
+
+#[test]
+fn should_decode_zrle_rectangles_from_one_zlib_stream() {
+    let mut client = Client::launch();
+    let buffer = RefCell::new(Vec::new());
+    client.complete_handshake(&buffer);
+
+    let mut zlib = flate2::write::ZlibEncoder::new(
+        Vec::new(), flate2::Compression::Default);
+    zlib.write_all(&[130,  3, 2, 1,  6, 5, 4,  0x81, 4,  0, 0, 0]).unwrap();
+    zlib.flush().unwrap();
+    let palette_rle : Vec<u8> = zlib.get_mut().drain(..).collect();
+    zlib.write_all(&[2,  0, 0, 10,  0, 0, 20,  0b1010_0000, 0b0101_0000])
+        .unwrap();
+    zlib.flush().unwrap();
+    let packed_palette : Vec<u8> = zlib.get_mut().drain(..).collect();
+
+    send_fb_update(&mut client, 2);
+    zrle_rectangle(0, 0, 4, 2, palette_rle.len())
+        .write(&mut client.socket).unwrap();
+    client.socket.write_all(&palette_rle).unwrap();
+    zrle_rectangle(0, 2, 4, 2, packed_palette.len())
+        .write(&mut client.socket).unwrap();
+    client.socket.write_all(&packed_palette).unwrap();
+
+    let fb = client.next_framebuffer();
+    assert_eq!(rgb_at(&fb, 4, 3, 0), [4, 5, 6]);
+    assert_eq!(rgb_at(&fb, 4, 0, 1), [4, 5, 6]);
+    assert_eq!(rgb_at(&fb, 4, 1, 1), [1, 2, 3]);
+    assert_eq!(rgb_at(&fb, 4, 3, 1), [1, 2, 3]);
+    assert_eq!(rgb_at(&fb, 4, 0, 2), [20, 0, 0]);
+    assert_eq!(rgb_at(&fb, 4, 1, 2), [10, 0, 0]);
+    assert_eq!(rgb_at(&fb, 4, 0, 3), [10, 0, 0]);
+    assert_eq!(rgb_at(&fb, 4, 3, 3), [20, 0, 0]);
+}
+
+#[test]
+fn should_decode_trle_tiles_and_reuse_palettes_within_a_rectangle() {
+    let mut client = Client::launch();
+    let buffer = RefCell::new(Vec::new());
+    client.complete_handshake(&buffer);
+
+    send_fb_update(&mut client, 2);
+    rfb::Rectangle {
+        x: 0,
+        y: 0,
+        width: 4,
+        height: 4,
+        payload: rfb::RectanglePayload::TrleRectangle(rfb::TrleRectangle { })
+    }.write(&mut client.socket).unwrap();
+    rfb::TrleTile::PaletteRle(rfb::TrlePaletteRle {
+        palette: vec![3, 2, 1,  6, 5, 4],
+        runs: vec![rfb::PaletteRun { index: 1, length: 5 },
+                   rfb::PaletteRun { index: 0, length: 11 }]
+    }).write(&mut client.socket, (3, 4, 4, 0)).unwrap();
+    rfb::Rectangle {
+        x: 0,
+        y: 3,
+        width: 4,
+        height: 1,
+        payload: rfb::RectanglePayload::TrleRectangle(rfb::TrleRectangle { })
+    }.write(&mut client.socket).unwrap();
+    rfb::TrleTile::PlainRle(rfb::TrlePlainRle {
+        runs: vec![rfb::TrleRun { color: vec![0, 0, 9], length: 4 }]
+    }).write(&mut client.socket, (3, 4, 1, 0)).unwrap();
+
+    let fb = client.next_framebuffer();
+    assert_eq!(rgb_at(&fb, 4, 3, 0), [4, 5, 6]);
+    assert_eq!(rgb_at(&fb, 4, 0, 1), [4, 5, 6]);
+    assert_eq!(rgb_at(&fb, 4, 1, 1), [1, 2, 3]);
+    assert_eq!(rgb_at(&fb, 4, 3, 2), [1, 2, 3]);
+    assert_eq!(rgb_at(&fb, 4, 2, 3), [9, 0, 0]);
+}
+
+fn hextile_rectangle(x : usize, y : usize, width : usize, height : usize)
+    -> rfb::Rectangle
+{
+    rfb::Rectangle {
+        x: x,
+        y: y,
+        width: width,
+        height: height,
+        payload: rfb::RectanglePayload::HextileRectangle(
+            rfb::HextileRectangle { })
+    }
+}
+
+#[test]
+fn should_decode_hextile_rectangles() {
+    let mut client = Client::launch();
+    let buffer = RefCell::new(Vec::new());
+    client.complete_handshake(&buffer);
+
+    send_fb_update(&mut client, 2);
+    hextile_rectangle(0, 0, 4, 2).write(&mut client.socket).unwrap();
+    rfb::HextileTile::Encoded(rfb::HextileEncoded {
+        background: Some(vec![3, 2, 1, 0]),
+        foreground: Some(vec![6, 5, 4, 0]),
+        subrects: Some(rfb::HextileSubrects::Foreground(vec![
+            rfb::HextileSubrect { position: (1, 1), size: (2, 1) }]))
+    }).write(&mut client.socket, (4, 4, 2)).unwrap();
+    hextile_rectangle(0, 2, 4, 2).write(&mut client.socket).unwrap();
+    rfb::HextileTile::Raw(rfb::HextileRaw {
+        pixels: (0..32).collect()
+    }).write(&mut client.socket, (4, 4, 2)).unwrap();
+
+    let fb = client.next_framebuffer();
+    assert_eq!(rgb_at(&fb, 4, 0, 0), [1, 2, 3]);
+    assert_eq!(rgb_at(&fb, 4, 0, 1), [1, 2, 3]);
+    assert_eq!(rgb_at(&fb, 4, 1, 1), [4, 5, 6]);
+    assert_eq!(rgb_at(&fb, 4, 2, 1), [4, 5, 6]);
+    assert_eq!(rgb_at(&fb, 4, 3, 1), [1, 2, 3]);
+    assert_eq!(rgb_at(&fb, 4, 1, 2), [6, 5, 4]);
+    assert_eq!(rgb_at(&fb, 4, 3, 3), [30, 29, 28]);
+}
+
+#[test]
+fn should_copy_overlapping_rectangles_after_decoding_the_ones_before() {
+    let mut client = Client::launch();
+    let buffer = RefCell::new(Vec::new());
+    client.complete_handshake(&buffer);
+
+    send_fb_update(&mut client, 3);
+    hextile_rectangle(0, 0, 4, 4).write(&mut client.socket).unwrap();
+    rfb::HextileTile::Raw(rfb::HextileRaw {
+        pixels: (0..16).flat_map(|i| vec![0, 0, i, 0]).collect()
+    }).write(&mut client.socket, (4, 4, 4)).unwrap();
+    rfb::Rectangle {
+        x: 1,
+        y: 1,
+        width: 3,
+        height: 3,
+        payload: rfb::RectanglePayload::CopyRectRectangle(
+            rfb::CopyRectRectangle {
+                src_x: 0,
+                src_y: 0
+            })
+    }.write(&mut client.socket).unwrap();
+    hextile_rectangle(0, 0, 1, 1).write(&mut client.socket).unwrap();
+    rfb::HextileTile::Encoded(rfb::HextileEncoded {
+        background: Some(vec![0, 0, 99, 0]),
+        foreground: None,
+        subrects: None
+    }).write(&mut client.socket, (4, 1, 1)).unwrap();
+
+    let fb = client.next_framebuffer();
+    assert_eq!(rgb_at(&fb, 4, 0, 0), [99, 0, 0]);
+    assert_eq!(rgb_at(&fb, 4, 1, 0), [1, 0, 0]);
+    assert_eq!(rgb_at(&fb, 4, 1, 1), [0, 0, 0]);
+    assert_eq!(rgb_at(&fb, 4, 3, 1), [2, 0, 0]);
+    assert_eq!(rgb_at(&fb, 4, 1, 3), [8, 0, 0]);
+    assert_eq!(rgb_at(&fb, 4, 3, 3), [10, 0, 0]);
+}
+
+#[test]
+fn should_fill_rre_and_corre_subrects_over_the_background() {
+    let mut client = Client::launch();
+    let buffer = RefCell::new(Vec::new());
+    client.complete_handshake(&buffer);
+
+    send_fb_update(&mut client, 2);
+    rfb::Rectangle {
+        x: 0,
+        y: 0,
+        width: 4,
+        height: 2,
+        payload: rfb::RectanglePayload::RreRectangle(rfb::RreRectangle {
+            no_of_subrects: 1
+        })
+    }.write(&mut client.socket).unwrap();
+    client.socket.write_all(&[3, 2, 1, 0]).unwrap();
+    rfb::RreSubrect {
+        color: vec![6, 5, 4, 0],
+        x: 1,
+        y: 1,
+        width: 3,
+        height: 1
+    }.write(&mut client.socket, (4,)).unwrap();
+    rfb::Rectangle {
+        x: 0,
+        y: 2,
+        width: 4,
+        height: 2,
+        payload: rfb::RectanglePayload::CorreRectangle(rfb::CorreRectangle {
+            no_of_subrects: 1
+        })
+    }.write(&mut client.socket).unwrap();
+    client.socket.write_all(&[9, 8, 7, 0]).unwrap();
+    rfb::CorreSubrect {
+        color: vec![0, 0, 0, 0],
+        x: 3,
+        y: 0,
+        width: 1,
+        height: 2
+    }.write(&mut client.socket, (4,)).unwrap();
+
+    let fb = client.next_framebuffer();
+    assert_eq!(rgb_at(&fb, 4, 0, 0), [1, 2, 3]);
+    assert_eq!(rgb_at(&fb, 4, 0, 1), [1, 2, 3]);
+    assert_eq!(rgb_at(&fb, 4, 1, 1), [4, 5, 6]);
+    assert_eq!(rgb_at(&fb, 4, 3, 1), [4, 5, 6]);
+    assert_eq!(rgb_at(&fb, 4, 2, 3), [7, 8, 9]);
+    assert_eq!(rgb_at(&fb, 4, 3, 2), [0, 0, 0]);
+    assert_eq!(rgb_at(&fb, 4, 3, 3), [0, 0, 0]);
+}
+
+#[test]
+fn should_fail_without_allocating_for_a_bogus_number_of_rre_subrects() {
+    let mut client = Client::launch();
+    let buffer = RefCell::new(Vec::new());
+    client.complete_handshake(&buffer);
+
+    send_fb_update(&mut client, 1);
+    rfb::Rectangle {
+        x: 0,
+        y: 0,
+        width: 4,
+        height: 2,
+        payload: rfb::RectanglePayload::RreRectangle(rfb::RreRectangle {
+            no_of_subrects: 0xffff_ffff
+        })
+    }.write(&mut client.socket).unwrap();
+    client.socket.write_all(&[3, 2, 1, 0]).unwrap();
+    client.socket.shutdown(Shutdown::Write).unwrap();
+
+    let error_message = client.should_exit_with_error();
+    assert_that!(error_message.to_lowercase()).contains("broken");
+}
+
+#[test]
+fn should_decode_zlib_rectangles_from_one_zlib_stream() {
+    let mut client = Client::launch();
+    let buffer = RefCell::new(Vec::new());
+    client.complete_handshake(&buffer);
+
+    let mut zlib = flate2::write::ZlibEncoder::new(
+        Vec::new(), flate2::Compression::Default);
+    let mut rectangles = Vec::new();
+    for y in 0..2 {
+        let pixels : Vec<u8> = (0..8).flat_map(|i| vec![0, 0, y * 8 + i, 0])
+            .collect();
+        zlib.write_all(&pixels).unwrap();
+        zlib.flush().unwrap();
+        rectangles.push(zlib.get_mut().drain(..).collect::<Vec<u8>>());
+    }
+
+    send_fb_update(&mut client, 2);
+    for (i, data) in rectangles.iter().enumerate() {
+        rfb::Rectangle {
+            x: 0,
+            y: i * 2,
+            width: 4,
+            height: 2,
+            payload: rfb::RectanglePayload::ZlibRectangle(rfb::ZlibRectangle {
+                length: data.len()
+            })
+        }.write(&mut client.socket).unwrap();
+        client.socket.write_all(data).unwrap();
+    }
+
+    let fb = client.next_framebuffer();
+    assert_eq!(rgb_at(&fb, 4, 1, 0), [1, 0, 0]);
+    assert_eq!(rgb_at(&fb, 4, 3, 1), [7, 0, 0]);
+    assert_eq!(rgb_at(&fb, 4, 0, 2), [8, 0, 0]);
+    assert_eq!(rgb_at(&fb, 4, 3, 3), [15, 0, 0]);
+}
+
+#[test]
+fn should_decode_zlib_hex_tiles_from_two_zlib_streams() {
+    let mut client = Client::launch();
+    let buffer = RefCell::new(Vec::new());
+    client.complete_handshake(&buffer);
+
+    let mut raw_stream = flate2::write::ZlibEncoder::new(
+        Vec::new(), flate2::Compression::Default);
+    let mut stream = flate2::write::ZlibEncoder::new(
+        Vec::new(), flate2::Compression::Default);
+    let compress = |zlib : &mut flate2::write::ZlibEncoder<Vec<u8>>,
+                        data : &[u8]| {
+        zlib.write_all(data).unwrap();
+        zlib.flush().unwrap();
+        zlib.get_mut().drain(..).collect::<Vec<u8>>()
+    };
+    let raw_pixels : Vec<u8> = (0..8).flat_map(|i| vec![0, 0, i, 0])
+        .collect();
+    let tiles = vec![
+        (0, 0, 4, 2, rfb::ZlibHexTile::ZlibRaw(
+                compress(&mut raw_stream, &raw_pixels))),
+        (0, 2, 4, 2, rfb::ZlibHexTile::Zlib {
+            flags: 2 | 4 | 8,
+            data: compress(&mut stream, &[3, 2, 1, 0,  6, 5, 4, 0,
+                                          1,  0x11, 0x00])
+        }),
+        (3, 3, 1, 1, rfb::ZlibHexTile::Zlib {
+            flags: 2,
+            data: compress(&mut stream, &[9, 9, 9, 0])
+        })];
+
+    send_fb_update(&mut client, 3);
+    for (x, y, width, height, tile) in tiles {
+        rfb::Rectangle {
+            x: x,
+            y: y,
+            width: width,
+            height: height,
+            payload: rfb::RectanglePayload::ZlibHexRectangle(
+                rfb::ZlibHexRectangle { })
+        }.write(&mut client.socket).unwrap();
+        tile.write(&mut client.socket, (4, width, height)).unwrap();
+    }
+
+    let fb = client.next_framebuffer();
+    assert_eq!(rgb_at(&fb, 4, 1, 0), [1, 0, 0]);
+    assert_eq!(rgb_at(&fb, 4, 3, 1), [7, 0, 0]);
+    assert_eq!(rgb_at(&fb, 4, 0, 2), [1, 2, 3]);
+    assert_eq!(rgb_at(&fb, 4, 1, 3), [4, 5, 6]);
+    assert_eq!(rgb_at(&fb, 4, 2, 3), [1, 2, 3]);
+    assert_eq!(rgb_at(&fb, 4, 3, 3), [9, 9, 9]);
+}
+
 //TODO parsers:
 //1. dynamic byte swap
 //x. tagged_meta_packet
@@ -287,3 +1655,254 @@ fn should_output_a_server_error_after_security_handshake() {
 //TODO only support true-color, document missing support for SetColorMapEntries
 
 //TODO re-use buffer, what goes wrong currently? -> probably nothing
+
+fn raw_rectangle(x : usize, y : usize, width : usize, height : usize)
+    -> rfb::Rectangle
+{
+    rfb::Rectangle {
+        x: x,
+        y: y,
+        width: width,
+        height: height,
+        payload: rfb::RectanglePayload::RawRectangle(rfb::RawRectangle { })
+    }
+}
+
+#[test]
+fn should_ask_for_and_decode_16_bit_pixels_if_configured() {
+    let mut client = Client::launch_with(|config| {
+        config.depth = flashvnc::PixelDepth::TrueColour16;
+    });
+    let buffer = RefCell::new(Vec::new());
+    let format = client.complete_handshake(&buffer);
+    assert_eq!((format.bits_per_pixel, format.true_color), (16, true));
+    assert_eq!((format.red_max, format.green_max, format.blue_max),
+               (31, 63, 31));
+    assert_eq!((format.red_shift, format.green_shift, format.blue_shift),
+               (11, 5, 0));
+
+    send_fb_update(&mut client, 2);
+    raw_rectangle(0, 0, 2, 1).write(&mut client.socket).unwrap();
+    client.socket.write_all(&[0x00, 0xf8,  0xe0, 0x07]).unwrap();
+    hextile_rectangle(0, 1, 4, 3).write(&mut client.socket).unwrap();
+    rfb::HextileTile::Encoded(rfb::HextileEncoded {
+        background: Some(vec![0x1f, 0x00]),
+        foreground: Some(vec![0xff, 0xff]),
+        subrects: Some(rfb::HextileSubrects::Foreground(vec![
+            rfb::HextileSubrect { position: (3, 2), size: (1, 1) }]))
+    }).write(&mut client.socket, (2, 4, 3)).unwrap();
+
+    let fb = client.next_framebuffer();
+    assert_eq!(rgb_at(&fb, 4, 0, 0), [255, 0, 0]);
+    assert_eq!(rgb_at(&fb, 4, 1, 0), [0, 255, 0]);
+    assert_eq!(rgb_at(&fb, 4, 0, 1), [0, 0, 255]);
+    assert_eq!(rgb_at(&fb, 4, 3, 3), [255, 255, 255]);
+}
+
+#[test]
+fn should_look_up_pixels_in_the_colour_map_sent_by_the_server() {
+    let mut client = Client::launch_with(|config| {
+        config.depth = flashvnc::PixelDepth::ColourMap;
+    });
+    let buffer = RefCell::new(Vec::new());
+    let format = client.complete_handshake(&buffer);
+    assert_eq!((format.bits_per_pixel, format.true_color), (8, false));
+
+    rfb::ServerToClient::SetColourMapEntries(rfb::SetColourMapEntries {
+        first_colour: 1,
+        colours: vec![
+            rfb::Colour { red: 0xffff, green: 0, blue: 0 },
+            rfb::Colour { red: 0, green: 0x8000, blue: 0xffff }]
+    }).write(&mut client.socket).unwrap();
+    send_fb_update(&mut client, 1);
+    raw_rectangle(0, 0, 4, 1).write(&mut client.socket).unwrap();
+    client.socket.write_all(&[1, 2, 0, 2]).unwrap();
+
+    let fb = client.next_framebuffer();
+    assert_eq!(rgb_at(&fb, 4, 0, 0), [255, 0, 0]);
+    assert_eq!(rgb_at(&fb, 4, 1, 0), [0, 128, 255]);
+    assert_eq!(rgb_at(&fb, 4, 2, 0), [0, 0, 0]);
+    assert_eq!(rgb_at(&fb, 4, 3, 0), [0, 128, 255]);
+}
+
+#[test]
+fn should_pass_latin1_clipboard_text_of_the_server_to_the_view() {
+    let mut client = Client::launch();
+    let buffer = RefCell::new(Vec::new());
+    client.complete_handshake(&buffer);
+
+    client.socket.write_all(&[3,  0, 0, 0,  0, 0, 0, 4]).unwrap();
+    client.socket.write_all(&[b'c', 0xe9, b'\n', 0xff]).unwrap();
+
+    assert_eq!(client.next_clipboard_text(), "c\u{e9}\n\u{ff}");
+}
+
+#[test]
+fn should_send_clipboard_text_of_the_view_unless_it_came_from_the_server() {
+    let mut client = Client::launch();
+    let buffer = RefCell::new(Vec::new());
+    client.complete_handshake(&buffer);
+
+    rfb::ServerToClient::ServerCutText(rfb::ServerCutText {
+        text: rfb::CutText::Latin1(String::from("from server"))
+    }).write(&mut client.socket).unwrap();
+    client.next_clipboard_text();
+    client.gui_events.send(flashvnc::GuiEvent::ClipboardText(
+            String::from("from server"))).unwrap();
+    client.gui_events.send(flashvnc::GuiEvent::ClipboardText(
+            String::from("\u{fc}ber 5\u{20ac}"))).unwrap();
+
+    loop {
+        match client.next_message(&buffer) {
+            rfb::ClientToServer::ClientCutText(cut_text) => {
+                assert_eq!(cut_text.text, rfb::CutText::Latin1(
+                        String::from("\u{fc}ber 5?")));
+                break;
+            },
+            _ => { }
+        }
+    }
+}
+
+fn next_cut_text(client : &Client, buffer : &RefCell<Vec<u8>>)
+    -> rfb::CutText
+{
+    loop {
+        match client.next_message(buffer) {
+            rfb::ClientToServer::ClientCutText(cut_text) =>
+                return cut_text.text,
+            _ => { }
+        }
+    }
+}
+
+fn send_extended_clipboard(client : &mut Client,
+                           message : rfb::ExtendedClipboard) {
+    rfb::ServerToClient::ServerCutText(rfb::ServerCutText {
+        text: rfb::CutText::Extended(message)
+    }).write(&mut client.socket).unwrap();
+}
+
+#[test]
+fn should_exchange_utf8_clipboard_text_with_the_extended_clipboard() {
+    let mut client = Client::launch();
+    let buffer = RefCell::new(Vec::new());
+    client.complete_handshake(&buffer);
+
+    send_extended_clipboard(&mut client, rfb::ExtendedClipboard::Caps {
+        flags: rfb::CLIPBOARD_TEXT | rfb::CLIPBOARD_REQUEST
+            | rfb::CLIPBOARD_NOTIFY | rfb::CLIPBOARD_PROVIDE,
+        max_sizes: vec![1024]
+    });
+    match next_cut_text(&client, &buffer) {
+        rfb::CutText::Extended(rfb::ExtendedClipboard::Caps { flags, .. }) =>
+            assert_eq!(flags & (rfb::CLIPBOARD_TEXT | rfb::CLIPBOARD_RTF
+                                | rfb::CLIPBOARD_HTML),
+                       rfb::CLIPBOARD_TEXT),
+        text => panic!("should send its caps, not {:?}", text)
+    }
+
+    send_extended_clipboard(&mut client, rfb::ExtendedClipboard::Notify(
+            rfb::CLIPBOARD_TEXT));
+    assert_eq!(next_cut_text(&client, &buffer), rfb::CutText::Extended(
+            rfb::ExtendedClipboard::Request(rfb::CLIPBOARD_TEXT)));
+    let text = "\u{3b1}\u{3b2}\r\n\u{20ac}\0".as_bytes();
+    let mut data = vec![0, 0, 0, text.len() as u8];
+    data.extend_from_slice(text);
+    send_extended_clipboard(&mut client, rfb::ExtendedClipboard::Provide {
+        formats: rfb::CLIPBOARD_TEXT,
+        data: zlib_compress(&data)
+    });
+    assert_eq!(client.next_clipboard_text(), "\u{3b1}\u{3b2}\n\u{20ac}");
+
+    client.gui_events.send(flashvnc::GuiEvent::ClipboardText(
+            String::from("\u{fc}"))).unwrap();
+    assert_eq!(next_cut_text(&client, &buffer), rfb::CutText::Extended(
+            rfb::ExtendedClipboard::Notify(rfb::CLIPBOARD_TEXT)));
+    send_extended_clipboard(&mut client, rfb::ExtendedClipboard::Request(
+            rfb::CLIPBOARD_TEXT));
+    match next_cut_text(&client, &buffer) {
+        rfb::CutText::Extended(rfb::ExtendedClipboard::Provide {
+            formats, data
+        }) => {
+            assert_eq!(formats, rfb::CLIPBOARD_TEXT);
+            let mut zlib = flate2::write::ZlibDecoder::new(Vec::new());
+            zlib.write_all(&data).unwrap();
+            assert_eq!(zlib.finish().unwrap(), [0, 0, 0, 3,  0xc3, 0xbc, 0]);
+        },
+        text => panic!("should provide the text, not {:?}", text)
+    }
+}
+
+#[test]
+fn should_ignore_malformed_extended_clipboard_data() {
+    let mut client = Client::launch();
+    let buffer = RefCell::new(Vec::new());
+    client.complete_handshake(&buffer);
+
+    send_extended_clipboard(&mut client, rfb::ExtendedClipboard::Provide {
+        formats: rfb::CLIPBOARD_TEXT,
+        data: vec![1, 2, 3]
+    });
+    rfb::ServerToClient::ServerCutText(rfb::ServerCutText {
+        text: rfb::CutText::Latin1(String::from("still connected"))
+    }).write(&mut client.socket).unwrap();
+    assert_eq!(client.next_clipboard_text(), "still connected");
+    client.join();
+}
+
+#[test]
+fn should_ring_the_bell_of_the_view_unless_it_is_off() {
+    for &bell in [flashvnc::BellMode::Visual, flashvnc::BellMode::Off].iter() {
+        let mut client = Client::launch_with(move |config| {
+            config.bell = bell;
+        });
+        let buffer = RefCell::new(Vec::new());
+        client.complete_handshake(&buffer);
+
+        rfb::ServerToClient::Bell(rfb::Bell { })
+            .write(&mut client.socket).unwrap();
+        rfb::ServerToClient::ServerCutText(rfb::ServerCutText {
+            text: rfb::CutText::Latin1(String::from("after the bell"))
+        }).write(&mut client.socket).unwrap();
+
+        let mut rang = false;
+        client.next_event_matching(|event| match event {
+            flashvnc::ProtocolEvent::Bell => {
+                rang = true;
+                None
+            },
+            flashvnc::ProtocolEvent::SetClipboardText(_) => Some(()),
+            _ => None
+        });
+        assert_eq!(rang, bell != flashvnc::BellMode::Off);
+        client.join();
+    }
+}
+
+#[test]
+fn should_set_the_title_to_the_changed_desktop_name() {
+    let mut client = Client::launch();
+    let buffer = RefCell::new(Vec::new());
+    client.complete_handshake(&buffer);
+
+    send_fb_update(&mut client, 1);
+    rfb::Rectangle {
+        x: 0,
+        y: 0,
+        width: 0,
+        height: 0,
+        payload: rfb::RectanglePayload::DesktopNameRectangle(
+            rfb::DesktopNameRectangle {
+                name: String::from("renamed d\u{e9}sktop")
+            })
+    }.write(&mut client.socket).unwrap();
+
+    let title = client.next_event_matching(|event| match event {
+        flashvnc::ProtocolEvent::SetTitle(title) =>
+            if title != "desktop" { Some(title) } else { None },
+        _ => None
+    });
+    assert_eq!(title, "renamed d\u{e9}sktop");
+    client.join();
+}